@@ -0,0 +1,11 @@
+-- Rebuild quotes with AUTOINCREMENT so ids of deleted quotes are never handed out again
+CREATE TABLE quotes_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id TEXT NOT NULL,
+    quote_date TEXT NOT NULL,
+    quote TEXT NOT NULL
+);
+INSERT INTO quotes_new (id, user_id, quote_date, quote)
+    SELECT id, user_id, quote_date, quote FROM quotes;
+DROP TABLE quotes;
+ALTER TABLE quotes_new RENAME TO quotes;
@@ -0,0 +1,3 @@
+-- Full time a quote was added, quote_date only keeps the day. Quotes added before this column
+-- existed have no known time and are left NULL.
+ALTER TABLE quotes ADD COLUMN created_at DATETIME;
@@ -0,0 +1,2 @@
+-- Discord id of whoever submitted the quote, unknown for quotes added before this column existed
+ALTER TABLE quotes ADD COLUMN added_by TEXT;
@@ -0,0 +1,3 @@
+-- Guild a quote belongs to, so one instance can serve several servers. Quotes added before this
+-- column existed are NULL and stay visible in every guild, like they were before.
+ALTER TABLE quotes ADD COLUMN guild_id TEXT;
@@ -0,0 +1,8 @@
+-- Stop new duplicates of quotes. Quotes already stored more than once are deleted first, except for
+-- their oldest copy, as the index couldn't be created otherwise. Nothing refers to quotes yet at
+-- this point, tags and votes only come later, so only the extra copies are dropped. Quotes are
+-- compared exactly, so the same text with different capitalization isn't a duplicate.
+DELETE FROM quotes WHERE id NOT IN (
+    SELECT MIN(id) FROM quotes GROUP BY guild_id, user_id, quote
+);
+CREATE UNIQUE INDEX quotes_unique_text ON quotes (guild_id, user_id, quote);
@@ -0,0 +1,7 @@
+-- Tags like "funny" or "cursed" attached to quotes, stored lowercase
+CREATE TABLE tags (
+    quote_id INTEGER NOT NULL REFERENCES quotes (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (quote_id, tag)
+);
+CREATE INDEX tags_by_tag ON tags (tag);
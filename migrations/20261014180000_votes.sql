@@ -0,0 +1,7 @@
+-- Up (1) or down (-1) votes on quotes, at most one per voter and quote
+CREATE TABLE votes (
+    quote_id INTEGER NOT NULL REFERENCES quotes (id) ON DELETE CASCADE,
+    voter_id TEXT NOT NULL,
+    vote INTEGER NOT NULL CHECK (vote IN (-1, 1)),
+    PRIMARY KEY (quote_id, voter_id)
+);
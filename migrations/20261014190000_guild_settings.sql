@@ -0,0 +1,5 @@
+-- Per-guild configuration, one row per guild that changed anything
+CREATE TABLE guild_settings (
+    guild_id TEXT PRIMARY KEY NOT NULL,
+    quote_of_the_day_channel TEXT
+);
@@ -0,0 +1,2 @@
+-- Per-guild override of --max-quote-length, NULL uses the command line value
+ALTER TABLE guild_settings ADD COLUMN max_quote_length INTEGER;
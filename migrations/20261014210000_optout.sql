@@ -0,0 +1,4 @@
+-- Users that don't want to be quoted, in any guild
+CREATE TABLE optout (
+    user_id TEXT PRIMARY KEY NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Messages that get enough of a guild's chosen reaction are saved automatically
+ALTER TABLE guild_settings ADD COLUMN reaction_emoji TEXT;
+ALTER TABLE guild_settings ADD COLUMN reaction_threshold INTEGER;
+-- The message a quote was saved from, so the same message isn't saved twice
+ALTER TABLE quotes ADD COLUMN message_id TEXT;
+CREATE UNIQUE INDEX quotes_unique_message ON quotes (message_id);
@@ -0,0 +1,2 @@
+-- Per-user lookups (list, count, random for a user, opting out) shouldn't scan every quote
+CREATE INDEX quotes_by_user ON quotes (user_id, guild_id);
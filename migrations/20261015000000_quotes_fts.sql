@@ -0,0 +1,14 @@
+-- Full-text index of quote text for /quote search, kept in sync with quotes by the triggers below
+CREATE VIRTUAL TABLE quotes_fts USING fts5 (quote, content = 'quotes', content_rowid = 'id');
+INSERT INTO quotes_fts (quotes_fts) VALUES ('rebuild');
+
+CREATE TRIGGER quotes_fts_insert AFTER INSERT ON quotes BEGIN
+    INSERT INTO quotes_fts (rowid, quote) VALUES (new.id, new.quote);
+END;
+CREATE TRIGGER quotes_fts_delete AFTER DELETE ON quotes BEGIN
+    INSERT INTO quotes_fts (quotes_fts, rowid, quote) VALUES ('delete', old.id, old.quote);
+END;
+CREATE TRIGGER quotes_fts_update AFTER UPDATE OF quote ON quotes BEGIN
+    INSERT INTO quotes_fts (quotes_fts, rowid, quote) VALUES ('delete', old.id, old.quote);
+    INSERT INTO quotes_fts (rowid, quote) VALUES (new.id, new.quote);
+END;
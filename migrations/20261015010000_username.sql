@@ -0,0 +1,2 @@
+-- Name of the quoted user when the quote was added, shown once they can't be looked up anymore
+ALTER TABLE quotes ADD COLUMN username TEXT;
@@ -0,0 +1,2 @@
+-- Role whose members may delete and edit quotes, besides those with Manage Messages
+ALTER TABLE guild_settings ADD COLUMN moderator_role TEXT;
@@ -0,0 +1,2 @@
+-- When a quote was deleted. Deleted quotes are hidden and can be restored until they're purged
+ALTER TABLE quotes ADD COLUMN deleted_at DATETIME;
@@ -0,0 +1,2 @@
+-- Channel of the message a quote was saved from, to link back to it along with message_id
+ALTER TABLE quotes ADD COLUMN channel_id TEXT;
@@ -0,0 +1,2 @@
+-- IANA name of the timezone dates are shown in, UTC when unset
+ALTER TABLE guild_settings ADD COLUMN timezone TEXT;
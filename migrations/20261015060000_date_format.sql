@@ -0,0 +1,2 @@
+-- strftime pattern dates are shown with, %Y-%m-%d when unset
+ALTER TABLE guild_settings ADD COLUMN date_format TEXT;
@@ -0,0 +1,17 @@
+-- Quotes submitted while their guild has moderation on, waiting for a moderator to approve them
+CREATE TABLE pending_quotes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    guild_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    -- JSON array of the tags to add along with the quote
+    tags TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    channel_id TEXT,
+    message_id TEXT,
+    created_at DATETIME NOT NULL
+);
+CREATE INDEX pending_quotes_by_guild ON pending_quotes (guild_id);
+
+ALTER TABLE guild_settings ADD COLUMN moderation BOOLEAN;
@@ -0,0 +1,2 @@
+-- Whether quotes are shown verbatim in code blocks instead of in bold
+ALTER TABLE guild_settings ADD COLUMN code_blocks BOOLEAN;
@@ -0,0 +1,2 @@
+-- Seconds a member has to wait between added quotes, 5 when unset and 0 for no wait
+ALTER TABLE guild_settings ADD COLUMN add_cooldown INTEGER;
@@ -0,0 +1,2 @@
+-- Whether quotes added on the same day in earlier years are posted with the quote of the day
+ALTER TABLE guild_settings ADD COLUMN anniversaries BOOLEAN;
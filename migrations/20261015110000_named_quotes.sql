@@ -0,0 +1,72 @@
+-- Let quotes be attributed by name alone, to people who were never in the server or aren't on
+-- Discord. Their user_id is NULL and username holds the name. Dropping the old table deletes every
+-- tag and vote through their foreign keys, so those are set aside and put back.
+CREATE TEMP TABLE tags_backup AS SELECT * FROM tags;
+CREATE TEMP TABLE votes_backup AS SELECT * FROM votes;
+CREATE TABLE quotes_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id TEXT,
+    quote_date TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    created_at DATETIME,
+    added_by TEXT,
+    guild_id TEXT,
+    message_id TEXT,
+    username TEXT,
+    deleted_at DATETIME,
+    channel_id TEXT,
+    CHECK (user_id IS NOT NULL OR username IS NOT NULL)
+);
+INSERT INTO quotes_new (id, user_id, quote_date, quote, created_at, added_by, guild_id, message_id,
+    username, deleted_at, channel_id)
+    SELECT id, user_id, quote_date, quote, created_at, added_by, guild_id, message_id, username,
+    deleted_at, channel_id FROM quotes;
+-- Ids of quotes that were removed for good still aren't handed out again
+DELETE FROM sqlite_sequence WHERE name = 'quotes_new';
+INSERT INTO sqlite_sequence (name, seq) SELECT 'quotes_new', seq FROM sqlite_sequence WHERE name = 'quotes';
+DROP TABLE quotes;
+ALTER TABLE quotes_new RENAME TO quotes;
+INSERT INTO tags SELECT * FROM tags_backup;
+INSERT INTO votes SELECT * FROM votes_backup;
+DROP TABLE tags_backup;
+DROP TABLE votes_backup;
+
+CREATE UNIQUE INDEX quotes_unique_text ON quotes (guild_id, user_id, quote);
+CREATE UNIQUE INDEX quotes_unique_message ON quotes (message_id);
+CREATE INDEX quotes_by_user ON quotes (user_id, guild_id);
+-- Named quotes have no user id to tell them apart, so it's their name
+CREATE UNIQUE INDEX quotes_unique_named_text ON quotes (guild_id, username, quote)
+    WHERE user_id IS NULL;
+
+CREATE TRIGGER quotes_fts_insert AFTER INSERT ON quotes BEGIN
+    INSERT INTO quotes_fts (rowid, quote) VALUES (new.id, new.quote);
+END;
+CREATE TRIGGER quotes_fts_delete AFTER DELETE ON quotes BEGIN
+    INSERT INTO quotes_fts (quotes_fts, rowid, quote) VALUES ('delete', old.id, old.quote);
+END;
+CREATE TRIGGER quotes_fts_update AFTER UPDATE OF quote ON quotes BEGIN
+    INSERT INTO quotes_fts (quotes_fts, rowid, quote) VALUES ('delete', old.id, old.quote);
+    INSERT INTO quotes_fts (rowid, quote) VALUES (new.id, new.quote);
+END;
+
+-- Quotes waiting for approval can be by name alone too
+CREATE TABLE pending_quotes_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    guild_id TEXT NOT NULL,
+    user_id TEXT,
+    username TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    -- JSON array of the tags to add along with the quote
+    tags TEXT NOT NULL,
+    added_by TEXT NOT NULL,
+    channel_id TEXT,
+    message_id TEXT,
+    created_at DATETIME NOT NULL
+);
+INSERT INTO pending_quotes_new SELECT id, guild_id, user_id, username, quote, tags, added_by,
+    channel_id, message_id, created_at FROM pending_quotes;
+DELETE FROM sqlite_sequence WHERE name = 'pending_quotes_new';
+INSERT INTO sqlite_sequence (name, seq) SELECT 'pending_quotes_new', seq FROM sqlite_sequence WHERE name = 'pending_quotes';
+DROP TABLE pending_quotes;
+ALTER TABLE pending_quotes_new RENAME TO pending_quotes;
+CREATE INDEX pending_quotes_by_guild ON pending_quotes (guild_id);
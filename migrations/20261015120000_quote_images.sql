@@ -0,0 +1,3 @@
+-- Link to an image shown along with a quote, like a screenshot of it
+ALTER TABLE quotes ADD COLUMN image_url TEXT;
+ALTER TABLE pending_quotes ADD COLUMN image_url TEXT;
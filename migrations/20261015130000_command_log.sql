@@ -0,0 +1,12 @@
+-- Commands invoked in guilds that turned usage logging on with /config usage-log, for /quote usage
+CREATE TABLE command_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    -- Full name of the command, like `quote add`
+    command TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    guild_id TEXT NOT NULL,
+    invoked_at DATETIME NOT NULL
+);
+CREATE INDEX command_log_by_guild ON command_log (guild_id, invoked_at);
+
+ALTER TABLE guild_settings ADD COLUMN usage_log BOOLEAN;
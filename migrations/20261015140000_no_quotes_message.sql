@@ -0,0 +1,2 @@
+-- Reply a guild set with /config no-quotes-message for when nothing matches
+ALTER TABLE guild_settings ADD COLUMN no_quotes_message TEXT;
@@ -0,0 +1,2 @@
+-- Most quotes a guild wants listed per page, set with /config results-per-page
+ALTER TABLE guild_settings ADD COLUMN results_per_page INTEGER;
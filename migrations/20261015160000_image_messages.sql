@@ -0,0 +1,6 @@
+-- Message an image of a quote is attached to, which a new link is fetched from once the stored one
+-- expired. Uploaded images are posted again by the bot for that.
+ALTER TABLE quotes ADD COLUMN image_channel_id TEXT;
+ALTER TABLE quotes ADD COLUMN image_message_id TEXT;
+ALTER TABLE pending_quotes ADD COLUMN image_channel_id TEXT;
+ALTER TABLE pending_quotes ADD COLUMN image_message_id TEXT;
@@ -0,0 +1,27 @@
+-- Quotes from before guilds were stored have no guild_id, and the unique index on the text treats
+-- every NULL as different, so it didn't stop duplicates of those. Copies of them stored more than
+-- once are deleted before indexing them on their own. The oldest copy that isn't deleted is kept,
+-- or the oldest one if all are, and gets the tags and votes of the others.
+CREATE TEMP TABLE duplicate_quotes AS
+    SELECT id, (
+        SELECT kept.id FROM quotes AS kept
+        WHERE kept.guild_id IS NULL AND kept.user_id IS quotes.user_id
+        AND (kept.user_id IS NOT NULL OR kept.username IS quotes.username)
+        AND kept.quote = quotes.quote
+        ORDER BY kept.deleted_at IS NOT NULL, kept.id LIMIT 1
+    ) AS kept_id
+    FROM quotes WHERE guild_id IS NULL;
+DELETE FROM duplicate_quotes WHERE id = kept_id;
+INSERT OR IGNORE INTO tags (quote_id, tag)
+    SELECT kept_id, tag FROM tags JOIN duplicate_quotes ON duplicate_quotes.id = tags.quote_id;
+-- A member who voted on several copies keeps their vote on the kept one
+INSERT OR IGNORE INTO votes (quote_id, voter_id, vote)
+    SELECT kept_id, voter_id, vote FROM votes
+    JOIN duplicate_quotes ON duplicate_quotes.id = votes.quote_id
+    ORDER BY duplicate_quotes.id;
+DELETE FROM quotes WHERE id IN (SELECT id FROM duplicate_quotes);
+DROP TABLE duplicate_quotes;
+CREATE UNIQUE INDEX quotes_unique_text_without_guild ON quotes (user_id, quote)
+    WHERE guild_id IS NULL AND user_id IS NOT NULL;
+CREATE UNIQUE INDEX quotes_unique_named_text_without_guild ON quotes (username, quote)
+    WHERE guild_id IS NULL AND user_id IS NULL;
@@ -0,0 +1,116 @@
+//! Options read from the file given with --config, for whatever isn't given on the command line.
+
+use crate::{parse_time, Cli};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Contents of a config file. Keys are named like the command line options, e.g.
+/// `max-connections = 10`.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct FileConfig {
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+    database: Option<String>,
+    max_connections: Option<u32>,
+    busy_timeout: Option<u64>,
+    max_quote_length: Option<usize>,
+    guild: Option<Guilds>,
+    register_globally: Option<bool>,
+    log_level: Option<String>,
+    quote_of_the_day_time: Option<String>,
+    recent_quotes: Option<usize>,
+    keep_deleted_days: Option<u32>,
+    reaction_quotes: Option<bool>,
+    prefix: Option<String>,
+    metrics_addr: Option<String>,
+}
+
+/// `guild` can be a single id or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Guilds {
+    One(u64),
+    Many(Vec<u64>),
+}
+
+/// Read the config file at `path` and use its values for every option of `cli` that wasn't given on
+/// the command line, as told by `matches`.
+pub fn apply(cli: &mut Cli, matches: &ArgMatches, path: &Path) -> Result<(), String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read config file {}: {}", path.display(), e))?;
+    let file: FileConfig = toml::from_str(&text)
+        .map_err(|e| format!("{} isn't a valid config file: {}", path.display(), e))?;
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    // Either kind of token on the command line replaces both from the file
+    if !given("token") && !given("token_file") {
+        if file.token.is_some() && file.token_file.is_some() {
+            return Err(format!(
+                "{} sets both token and token-file, only one can be used",
+                path.display()
+            ));
+        }
+        if file.token.is_some() {
+            cli.token = file.token;
+        }
+        if file.token_file.is_some() {
+            cli.token_file = file.token_file;
+        }
+    }
+    if let (Some(database), false) = (file.database, given("database")) {
+        cli.database = Some(database);
+    }
+    if let (Some(max_connections), false) = (file.max_connections, given("max_connections")) {
+        if max_connections == 0 {
+            return Err(format!(
+                "max-connections in {} has to be at least 1",
+                path.display()
+            ));
+        }
+        cli.max_connections = max_connections;
+    }
+    if let (Some(busy_timeout), false) = (file.busy_timeout, given("busy_timeout")) {
+        cli.busy_timeout = busy_timeout;
+    }
+    if let (Some(max_quote_length), false) = (file.max_quote_length, given("max_quote_length")) {
+        cli.max_quote_length = max_quote_length;
+    }
+    if let (Some(guilds), false) = (file.guild, given("guild")) {
+        cli.guild = match guilds {
+            Guilds::One(guild) => vec![guild],
+            Guilds::Many(guilds) => guilds,
+        };
+    }
+    if let (Some(register_globally), false) = (file.register_globally, given("register_globally")) {
+        cli.register_globally = register_globally;
+    }
+    if let (Some(log_level), false) = (file.log_level, given("log_level")) {
+        cli.log_level = log_level;
+    }
+    if let (Some(time), false) = (file.quote_of_the_day_time, given("quote_of_the_day_time")) {
+        cli.quote_of_the_day_time = parse_time(&time)
+            .map_err(|e| format!("quote-of-the-day-time in {}: {}", path.display(), e))?;
+    }
+    if let (Some(recent_quotes), false) = (file.recent_quotes, given("recent_quotes")) {
+        cli.recent_quotes = recent_quotes;
+    }
+    if let (Some(keep_deleted_days), false) = (file.keep_deleted_days, given("keep_deleted_days")) {
+        cli.keep_deleted_days = keep_deleted_days;
+    }
+    if let (Some(reaction_quotes), false) = (file.reaction_quotes, given("reaction_quotes")) {
+        cli.reaction_quotes = reaction_quotes;
+    }
+    if let (Some(prefix), false) = (file.prefix, given("prefix")) {
+        cli.prefix = Some(prefix);
+    }
+    if let (Some(addr), false) = (file.metrics_addr, given("metrics_addr")) {
+        let addr = addr
+            .parse()
+            .map_err(|e| format!("metrics-addr in {}: {}", path.display(), e))?;
+        cli.metrics_addr = Some(addr);
+    }
+    Ok(())
+}
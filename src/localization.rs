@@ -0,0 +1,164 @@
+//! Translated names and descriptions of the slash commands, shown by Discord to members using it in
+//! one of these languages. A new language only needs its entries added to `LOCALIZATIONS`.
+
+/// Translation of one command into one language.
+pub struct Localization {
+    /// Full name of the command, like `quote add`.
+    pub command: &'static str,
+    /// Discord locale, like `pl` or `pt-BR`.
+    pub locale: &'static str,
+    /// Lowercase and without spaces, like the untranslated names.
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Translated descriptions of its parameters, by their untranslated names.
+    pub parameters: &'static [(&'static str, &'static str)],
+}
+
+pub const LOCALIZATIONS: &[Localization] = &[
+    Localization {
+        command: "quote",
+        locale: "pl",
+        name: "cytat",
+        description: "Zapisuj i przeglądaj cytaty z tego serwera",
+        parameters: &[],
+    },
+    Localization {
+        command: "quote",
+        locale: "de",
+        name: "zitat",
+        description: "Zitate aus diesem Server speichern und ansehen",
+        parameters: &[],
+    },
+    Localization {
+        command: "quote add",
+        locale: "pl",
+        name: "dodaj",
+        description: "Dodaj cytat użytkownika albo zapisz wiadomość z linku lub id jako cytat jej autora",
+        parameters: &[
+            ("user", "Wybrany użytkownik"),
+            ("quote", "Cytat, który chcesz dodać"),
+            (
+                "message",
+                "Link lub id wiadomości do zacytowania zamiast tego, np. tej, na którą odpowiadasz",
+            ),
+            ("tags", "Tagi oddzielone przecinkami, np.: śmieszne, przeklęte"),
+            ("image", "Obraz do pokazania z cytatem, np. jego zrzut ekranu"),
+            ("image_url", "Link do obrazu do pokazania z cytatem zamiast pliku"),
+        ],
+    },
+    Localization {
+        command: "quote add",
+        locale: "de",
+        name: "hinzufügen",
+        description: "Ein Zitat hinzufügen oder eine Nachricht per Link oder ID als Zitat ihres Autors speichern",
+        parameters: &[
+            ("user", "Ausgewählter Nutzer"),
+            ("quote", "Zitat, das du hinzufügen willst"),
+            (
+                "message",
+                "Link oder ID einer Nachricht, die stattdessen zitiert wird, z. B. die beantwortete",
+            ),
+            ("tags", "Durch Kommas getrennte Tags, z. B.: lustig, verflucht"),
+            ("image", "Bild, das mit dem Zitat gezeigt wird, z. B. ein Screenshot davon"),
+            ("image_url", "Link zu einem Bild, das stattdessen mit dem Zitat gezeigt wird"),
+        ],
+    },
+    Localization {
+        command: "quote random",
+        locale: "pl",
+        name: "losowy",
+        description: "Pokaż losowe cytaty podanych użytkowników albo kogokolwiek, jeśli nikogo nie podano",
+        parameters: &[
+            ("user", "Wybrany użytkownik"),
+            ("tag", "Wybieraj tylko cytaty z tym tagiem"),
+            ("count", "Ile cytatów pokazać, najwyżej 5"),
+            ("after", "Tylko cytaty dodane tego dnia lub później, np. 2023-06-01"),
+            ("before", "Tylko cytaty dodane tego dnia lub wcześniej, np. 2023-08-31"),
+            ("min_score", "Tylko cytaty z co najmniej takim wynikiem z głosów"),
+            ("min_length", "Tylko cytaty mające co najmniej tyle znaków"),
+            ("max_length", "Tylko cytaty mające najwyżej tyle znaków"),
+            ("user2", "Kolejny użytkownik do wyboru"),
+            ("user3", "Kolejny użytkownik do wyboru"),
+            ("user4", "Kolejny użytkownik do wyboru"),
+            ("user5", "Kolejny użytkownik do wyboru"),
+            (
+                "name",
+                "Wybieraj też z cytatów osoby o tym imieniu, np. dodanych przez /quote addname",
+            ),
+            (
+                "mode",
+                "Wybieraj wszystkie cytaty jednakowo albo częściej te dodane niedawno",
+            ),
+            (
+                "verbose",
+                "Pokaż też, kto zgłosił cytaty i z jakich wiadomości je zapisano",
+            ),
+        ],
+    },
+    Localization {
+        command: "quote random",
+        locale: "de",
+        name: "zufällig",
+        description: "Zufällige Zitate der angegebenen Nutzer zeigen, oder von allen, wenn keiner angegeben ist",
+        parameters: &[
+            ("user", "Ausgewählter Nutzer"),
+            ("tag", "Nur Zitate mit diesem Tag auswählen"),
+            ("count", "Wie viele Zitate gezeigt werden, höchstens 5"),
+            (
+                "after",
+                "Nur Zitate, die an oder nach diesem Tag hinzugefügt wurden, z. B. 2023-06-01",
+            ),
+            (
+                "before",
+                "Nur Zitate, die an oder vor diesem Tag hinzugefügt wurden, z. B. 2023-08-31",
+            ),
+            ("min_score", "Nur Zitate mit mindestens dieser Bewertung aus Abstimmungen"),
+            ("min_length", "Nur Zitate mit mindestens so vielen Zeichen"),
+            ("max_length", "Nur Zitate mit höchstens so vielen Zeichen"),
+            ("user2", "Ein weiterer Nutzer, aus dessen Zitaten gewählt wird"),
+            ("user3", "Ein weiterer Nutzer, aus dessen Zitaten gewählt wird"),
+            ("user4", "Ein weiterer Nutzer, aus dessen Zitaten gewählt wird"),
+            ("user5", "Ein weiterer Nutzer, aus dessen Zitaten gewählt wird"),
+            (
+                "name",
+                "Auch aus Zitaten unter diesem Namen wählen, z. B. mit /quote addname hinzugefügten",
+            ),
+            (
+                "mode",
+                "Alle Zitate gleich oft wählen oder kürzlich hinzugefügte öfter",
+            ),
+            (
+                "verbose",
+                "Auch zeigen, wer die Zitate eingereicht hat und aus welchen Nachrichten sie stammen",
+            ),
+        ],
+    },
+];
+
+/// Add the translations in `LOCALIZATIONS` to `commands` and their subcommands, which are named
+/// after `parent` when given.
+pub fn localize<U, E>(commands: &mut [poise::Command<U, E>], parent: Option<&str>) {
+    for command in commands {
+        let path = match parent {
+            Some(parent) => format!("{} {}", parent, command.name),
+            None => command.name.clone(),
+        };
+        for localization in LOCALIZATIONS.iter().filter(|l| l.command == path) {
+            let locale = localization.locale.to_string();
+            command
+                .name_localizations
+                .insert(locale.clone(), localization.name.to_string());
+            command
+                .description_localizations
+                .insert(locale.clone(), localization.description.to_string());
+            for &(name, description) in localization.parameters {
+                if let Some(parameter) = command.parameters.iter_mut().find(|p| p.name == name) {
+                    parameter
+                        .description_localizations
+                        .insert(locale.clone(), description.to_string());
+                }
+            }
+        }
+        localize(&mut command.subcommands, Some(&path));
+    }
+}
@@ -1,67 +1,634 @@
-use chrono::Utc;
-use clap::Parser;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use poise::serenity_prelude as serenity;
-use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePoolOptions};
-use sqlx::{migrate::Migrator, query, Pool};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serenity::Mentionable;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{
+    Sqlite, SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions,
+};
+use sqlx::{query, query_as, Pool};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+mod config;
+mod localization;
+mod maintenance;
+mod metrics;
 
 // Whenever somebody joins slap a quote of theirs
 
 #[derive(Parser)]
 #[command(version, about)]
 struct Cli {
-    /// Access token for your bot. Required to run.
-    #[arg(short, long, required(true))]
-    token: String,
-    /// Path to where the database should be stored, relative to the current path. If it doesn't exist it will
-    /// be created. By default it will use './database.sqlite'.
+    /// Read options from this TOML file, with keys named like the options, e.g.
+    /// `max-connections = 10`. Options given on the command line take precedence over the file,
+    /// and the file over the defaults.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Access token for your bot. Required to run, read from the DISCORD_TOKEN environment variable
+    /// if neither this nor --token-file is given. Prefer the alternatives where possible, arguments
+    /// are visible to other users.
     #[arg(short, long)]
+    token: Option<String>,
+    /// Read the access token from this file instead, e.g. a secret mounted with restricted permissions.
+    #[arg(long, conflicts_with = "token")]
+    token_file: Option<PathBuf>,
+    /// Path to where the database should be stored, relative to the current path. If it doesn't exist it will
+    /// be created. By default it will use './database.sqlite'. ':memory:' keeps it in memory
+    /// instead, so everything is lost when the bot stops.
+    #[arg(short, long, global = true)]
     database: Option<String>,
-    /// Optional guild id to connect to. Useful for testing, speeds up registering of commands.
+    /// How many connections to the database can be open at once.
+    #[arg(long, global = true, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    max_connections: u32,
+    /// How many seconds a query waits for another connection's write to finish before failing
+    /// with "database is locked".
+    #[arg(long, global = true, default_value_t = 5)]
+    busy_timeout: u64,
+    /// Longest quote, in characters, that can be added.
+    #[arg(long, default_value_t = 1000)]
+    max_quote_length: usize,
+    /// Optional guild id to connect to, can be given several times. Useful for testing, commands are
+    /// registered only in those guilds, where they show up right away. If none of them works they
+    /// are registered globally.
     #[arg(short, long)]
-    guild: Option<u64>,
-    /// Print a premade systemd unit with your options.
+    guild: Vec<u64>,
+    /// Register commands globally even when --guild is given, which can take up to an hour to show
+    /// up everywhere.
+    #[arg(long)]
+    register_globally: bool,
+    /// Which messages to log, either a level (error, warn, info, debug, trace) or a full filter
+    /// like 'discord_quote_bot=debug,serenity=warn'. By default executed queries aren't logged.
+    #[arg(long, default_value = "info,sqlx=warn")]
+    log_level: String,
+    /// Time of day, in UTC, at which the quote of the day is posted.
+    #[arg(long, default_value = "12:00", value_parser = parse_time)]
+    quote_of_the_day_time: NaiveTime,
+    /// How many of the last quotes shown by /quote random in a channel are left out of the next
+    /// picks, 0 allows repeats right away.
+    #[arg(long, default_value_t = 10)]
+    recent_quotes: usize,
+    /// How many days deleted quotes can be restored for, after that they're removed for good.
+    #[arg(long, default_value_t = 30)]
+    keep_deleted_days: u32,
+    /// Let servers save messages as quotes once they get enough reactions, see /config
+    /// reaction-quotes. Needs the privileged message content intent to be enabled for the bot.
+    #[arg(long)]
+    reaction_quotes: bool,
+    /// Also take the /quote commands as messages starting with this prefix, like
+    /// `!quote random @user`. Needs the privileged message content intent to be enabled for the bot.
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Remove the bot's slash commands, globally and in each --guild given, and exit. Useful when
+    /// renamed or removed commands still show up.
+    #[arg(long)]
+    unregister: bool,
+    /// Register the bot's slash commands like it does when starting, globally or in each --guild
+    /// given, and exit. Useful to update them as a separate deployment step.
+    #[arg(long, conflicts_with = "unregister")]
+    register_only: bool,
+    /// Serve metrics in the Prometheus format at /metrics on this address, like 127.0.0.1:9100.
+    /// Nothing is served by default.
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+    /// Print a premade systemd unit with your options. It reads the token from an environment
+    /// file, so none needs to be given.
     #[arg(long = "make-systemd-unit")]
     unit: bool,
+    /// User the systemd unit runs the bot as.
+    #[arg(long, requires = "unit", default_value = "discord_quote_bot")]
+    unit_user: String,
+    /// Group the systemd unit runs the bot as, by default the same as --unit-user.
+    #[arg(long, requires = "unit")]
+    unit_group: Option<String>,
+    /// Maintenance task to run instead of the bot.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply pending database migrations and exit, without starting the bot.
+    Migrate,
+    /// Write every stored quote that isn't deleted as JSON, e.g. for backups or moving to another host.
+    Export {
+        /// File to write to, by default the quotes are printed.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Add quotes from a JSON file made by export. Nothing is imported if any of them fail.
+    Import {
+        /// File made by export.
+        input: PathBuf,
+        /// Leave out quotes that are already stored for the same user, instead of failing.
+        #[arg(long)]
+        skip_duplicates: bool,
+    },
+    /// Rebuild the database file to give back the space of removed quotes, and exit. Best run while
+    /// the bot is stopped, it needs up to twice the file's size on disk.
+    Vacuum,
+    /// Write a consistent copy of the database, safe to take while the bot is running, and exit.
+    Backup {
+        /// File to write the copy to, it must not exist yet.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// `(user_id, username, quote count)` of the most quoted, most first. People given only by name have
+/// no user id and are told apart by their name.
+type Leaderboard = Vec<(Option<String>, Option<String>, i64)>;
+
+/// Ids of the quotes /quote random can pick from, shared so picking doesn't copy them.
+type QuoteIds = Arc<Vec<i64>>;
+
+/// `(guild_id, user_id)` of a set of cached quote ids, without a user for everyone's quotes.
+type QuoteIdsKey = (String, Option<String>);
+
+/// Configuration a guild changed with /config, unset values fall back to the defaults.
+#[derive(Clone, Default)]
+struct GuildSettings {
+    quote_of_the_day_channel: Option<String>,
+    max_quote_length: Option<i64>,
+    /// Reaction that saves a message as a quote, in the form `ReactionType` displays as.
+    reaction_emoji: Option<String>,
+    /// How many of `reaction_emoji` a message needs to be saved.
+    reaction_threshold: Option<i64>,
+    /// Role that may delete and edit quotes, see `is_moderator`.
+    moderator_role: Option<String>,
+    /// IANA name of the timezone dates are shown in.
+    timezone: Option<String>,
+    /// strftime pattern dates are shown with, checked by /config date-format.
+    date_format: Option<String>,
+    /// Whether quotes added by members other than moderators wait for a moderator's approval.
+    moderation: Option<bool>,
+    /// Whether quotes are shown verbatim in code blocks instead of in bold.
+    code_blocks: Option<bool>,
+    /// Seconds a member has to wait between added quotes.
+    add_cooldown: Option<i64>,
+    /// Whether quotes added on the same day in earlier years are posted with the quote of the day.
+    anniversaries: Option<bool>,
+    /// Whether invoked commands are logged for /quote usage.
+    usage_log: Option<bool>,
+    /// Reply when no quotes match, with `{user}` standing for whose were looked for.
+    no_quotes_message: Option<String>,
+    /// Most quotes shown on one page of a list, and by /quote random at once.
+    results_per_page: Option<i64>,
+}
+
+/// How a guild wants quotes shown, see /config timezone, /config date-format and
+/// /config code-blocks.
+struct QuoteStyle {
+    timezone: Tz,
+    format: String,
+    code_blocks: bool,
+}
+
+impl QuoteStyle {
+    fn new(timezone: Option<&str>, format: Option<&str>, code_blocks: Option<bool>) -> Self {
+        Self {
+            timezone: timezone
+                .and_then(|timezone| timezone.parse().ok())
+                .unwrap_or(Tz::UTC),
+            format: format.unwrap_or(DEFAULT_DATE_FORMAT).to_string(),
+            code_blocks: code_blocks.unwrap_or(false),
+        }
+    }
 }
 
 struct Data {
     database: Pool<Sqlite>,
+    /// Used by guilds that didn't set their own maximum.
+    default_max_quote_length: usize,
+    /// Last computed leaderboard of each guild, with the time it was computed.
+    leaderboards: Mutex<HashMap<String, (Instant, Leaderboard)>>,
+    /// Copy of the guild_settings table, kept up to date by /config.
+    settings: Mutex<HashMap<String, GuildSettings>>,
+    /// How long deleted quotes can be restored before they're purged.
+    keep_deleted_days: u32,
+    /// How many recently shown quotes /quote random avoids.
+    recent_quotes_window: usize,
+    /// Ids of the quotes last shown by /quote random in each channel, newest last, with when the
+    /// channel last used it.
+    recent_quotes: Mutex<HashMap<serenity::ChannelId, (Instant, VecDeque<i64>)>>,
+    /// Ids of the quotes in each guild, by guild and quoted user, with when they were fetched. Lets
+    /// /quote random pick in memory instead of having SQLite sort every matching quote.
+    quote_ids: Mutex<HashMap<QuoteIdsKey, (Instant, QuoteIds)>>,
+    /// When the bot started, for /uptime.
+    started: Instant,
+    /// When each member last added a quote in each guild, for the cooldown of /quote add.
+    last_added: Mutex<HashMap<(String, serenity::UserId), Instant>>,
+    /// Picks random quotes, seeded from entropy outside of tests.
+    rng: Mutex<StdRng>,
+    /// Served with --metrics-addr.
+    metrics: Arc<metrics::Metrics>,
+    /// Whether the bot was started with --reaction-quotes, without which /config reaction-quotes
+    /// does nothing.
+    reaction_quotes: bool,
 }
 
 impl Data {
-    async fn migrate(&self) {
-        static MIGRATOR: Migrator = sqlx::migrate!();
-        MIGRATOR.run(&self.database).await.unwrap();
+    async fn migrate(&self) -> Result<(), Error> {
+        MIGRATOR.run(&self.database).await?;
+        Ok(())
+    }
+    /// Fill the settings cache from the database.
+    async fn load_settings(&self) -> Result<(), Error> {
+        let rows = query!("SELECT * FROM guild_settings;")
+            .fetch_all(&self.database)
+            .await?;
+        let mut settings = self.settings.lock().unwrap();
+        for row in rows {
+            settings.insert(
+                row.guild_id,
+                GuildSettings {
+                    quote_of_the_day_channel: row.quote_of_the_day_channel,
+                    max_quote_length: row.max_quote_length,
+                    reaction_emoji: row.reaction_emoji,
+                    reaction_threshold: row.reaction_threshold,
+                    moderator_role: row.moderator_role,
+                    timezone: row.timezone,
+                    date_format: row.date_format,
+                    moderation: row.moderation,
+                    code_blocks: row.code_blocks,
+                    add_cooldown: row.add_cooldown,
+                    anniversaries: row.anniversaries,
+                    usage_log: row.usage_log,
+                    no_quotes_message: row.no_quotes_message,
+                    results_per_page: row.results_per_page,
+                },
+            );
+        }
+        Ok(())
     }
-    async fn from(guild: Option<u64>, db: Option<String>) -> Self {
-        let path = if let Some(db) = db {
-            db
+    /// Re-read the settings of one guild after they were changed.
+    async fn refresh_settings(&self, guild_id: &str) -> Result<(), Error> {
+        let row = query!("SELECT * FROM guild_settings WHERE guild_id = ?;", guild_id)
+            .fetch_optional(&self.database)
+            .await?;
+        let mut settings = self.settings.lock().unwrap();
+        if let Some(row) = row {
+            settings.insert(
+                row.guild_id,
+                GuildSettings {
+                    quote_of_the_day_channel: row.quote_of_the_day_channel,
+                    max_quote_length: row.max_quote_length,
+                    reaction_emoji: row.reaction_emoji,
+                    reaction_threshold: row.reaction_threshold,
+                    moderator_role: row.moderator_role,
+                    timezone: row.timezone,
+                    date_format: row.date_format,
+                    moderation: row.moderation,
+                    code_blocks: row.code_blocks,
+                    add_cooldown: row.add_cooldown,
+                    anniversaries: row.anniversaries,
+                    usage_log: row.usage_log,
+                    no_quotes_message: row.no_quotes_message,
+                    results_per_page: row.results_per_page,
+                },
+            );
         } else {
-            "database.sqlite".to_string()
+            settings.remove(guild_id);
+        }
+        Ok(())
+    }
+    fn settings(&self, guild_id: &str) -> GuildSettings {
+        self.settings
+            .lock()
+            .unwrap()
+            .get(guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+    fn recent_quotes(&self, channel: serenity::ChannelId) -> Vec<i64> {
+        self.recent_quotes
+            .lock()
+            .unwrap()
+            .get(&channel)
+            .map(|(_, ids)| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+    /// Note that quote `id` was shown in `channel`, and drop the history of channels that haven't
+    /// been used in a while.
+    fn remember_recent_quote(&self, channel: serenity::ChannelId, id: i64) {
+        if self.recent_quotes_window == 0 {
+            return;
+        }
+        let mut recent = self.recent_quotes.lock().unwrap();
+        recent.retain(|_, (used, _)| used.elapsed() < RECENT_QUOTES_TTL);
+        let (used, ids) = recent
+            .entry(channel)
+            .or_insert_with(|| (Instant::now(), VecDeque::new()));
+        *used = Instant::now();
+        ids.push_back(id);
+        while ids.len() > self.recent_quotes_window {
+            ids.pop_front();
+        }
+    }
+    /// How long `user` still has to wait before adding another quote in the guild with id
+    /// `guild_id`, counting from the last one noted with `note_added`.
+    fn add_cooldown(&self, guild_id: &str, user: serenity::UserId) -> Option<Duration> {
+        let cooldown = self
+            .settings(guild_id)
+            .add_cooldown
+            .map(|seconds| Duration::from_secs(seconds as u64))
+            .unwrap_or(DEFAULT_ADD_COOLDOWN);
+        let mut last_added = self.last_added.lock().unwrap();
+        last_added.retain(|_, added| added.elapsed() < MAX_ADD_COOLDOWN);
+        let added = last_added.get(&(guild_id.to_string(), user))?;
+        cooldown
+            .checked_sub(added.elapsed())
+            .filter(|wait| !wait.is_zero())
+    }
+    /// Start the cooldown of `user` in the guild with id `guild_id`, once a quote of theirs was
+    /// stored or queued for approval.
+    fn note_added(&self, guild_id: &str, user: serenity::UserId) {
+        self.last_added
+            .lock()
+            .unwrap()
+            .insert((guild_id.to_string(), user), Instant::now());
+    }
+    fn forget_recent_quotes(&self, channel: serenity::ChannelId) {
+        self.recent_quotes.lock().unwrap().remove(&channel);
+    }
+    /// Ids of the quotes by the user with id `user_id`, or by anyone, in the guild with id
+    /// `guild_id`. Fetched again once the cached ones are older than `QUOTE_IDS_CACHE_TTL`.
+    async fn quote_ids(&self, guild_id: &str, user_id: Option<&str>) -> Result<QuoteIds, Error> {
+        let key = (guild_id.to_string(), user_id.map(str::to_string));
+        let cached = self
+            .quote_ids
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(fetched, _)| fetched.elapsed() < QUOTE_IDS_CACHE_TTL)
+            .map(|(_, ids)| ids.clone());
+        if let Some(ids) = cached {
+            return Ok(ids);
+        }
+        let ids: Vec<i64> = query!(
+            "SELECT id FROM quotes WHERE (?1 IS NULL OR user_id = ?1)
+            AND (guild_id = ?2 OR guild_id IS NULL) AND deleted_at IS NULL;",
+            user_id,
+            guild_id
+        )
+        .fetch_all(&self.database)
+        .await?
+        .into_iter()
+        .map(|entry| entry.id)
+        .collect();
+        let ids = Arc::new(ids);
+        let mut cache = self.quote_ids.lock().unwrap();
+        cache.retain(|_, (fetched, _)| fetched.elapsed() < QUOTE_IDS_CACHE_TTL);
+        cache.insert(key, (Instant::now(), ids.clone()));
+        Ok(ids)
+    }
+    /// Drop the cached quote ids after quotes were added or removed. Quotes without a guild are in
+    /// every guild, so it's all of them.
+    fn forget_quote_ids(&self) {
+        self.quote_ids.lock().unwrap().clear();
+    }
+    /// How quotes are shown, in bold with UTC and ISO 8601 dates unless the guild picked something
+    /// else.
+    fn quote_style(&self, guild_id: &str) -> QuoteStyle {
+        let settings = self.settings(guild_id);
+        QuoteStyle::new(
+            settings.timezone.as_deref(),
+            settings.date_format.as_deref(),
+            settings.code_blocks,
+        )
+    }
+    fn max_quote_length(&self, guild_id: &str) -> usize {
+        self.settings(guild_id)
+            .max_quote_length
+            .map(|length| length as usize)
+            .unwrap_or(self.default_max_quote_length)
+    }
+    fn results_per_page(&self, guild_id: &str) -> usize {
+        self.settings(guild_id)
+            .results_per_page
+            .map(|count| count as usize)
+            .unwrap_or(QUOTES_PER_PAGE)
+    }
+    /// The reply for when none of the quotes of `names` match, in the guild's own words if it set
+    /// them with /config no-quotes-message. Those can mention anyone, so it has to be sent without
+    /// allowing mentions.
+    fn no_quotes_message(&self, guild_id: &str, names: &[&str]) -> String {
+        match self.settings(guild_id).no_quotes_message {
+            Some(template) => fill_no_quotes_message(&template, &names.join(", ")),
+            None if names.len() > 1 => format!("No quotes found for users: {}", names.join(", ")),
+            None => fill_no_quotes_message(DEFAULT_NO_QUOTES_MESSAGE, &names.join(", ")),
+        }
+    }
+    async fn from(
+        path: &str,
+        max_connections: u32,
+        busy_timeout: Duration,
+        max_quote_length: usize,
+        recent_quotes_window: usize,
+        keep_deleted_days: u32,
+        reaction_quotes: bool,
+    ) -> Result<Self, Error> {
+        let database = connect(path, max_connections, busy_timeout).await?;
+        let out = Self {
+            database,
+            default_max_quote_length: max_quote_length,
+            leaderboards: Mutex::new(HashMap::new()),
+            settings: Mutex::new(HashMap::new()),
+            keep_deleted_days,
+            recent_quotes_window,
+            recent_quotes: Mutex::new(HashMap::new()),
+            quote_ids: Mutex::new(HashMap::new()),
+            started: Instant::now(),
+            last_added: Mutex::new(HashMap::new()),
+            rng: Mutex::new(StdRng::from_entropy()),
+            metrics: Arc::default(),
+            reaction_quotes,
         };
+        out.migrate()
+            .await
+            .map_err(|e| format!("Couldn't migrate database: {}", e))?;
+        out.load_settings()
+            .await
+            .map_err(|e| format!("Couldn't load guild settings: {}", e))?;
+        info!("Opened database {}", path);
+        Ok(out)
+    }
+    /// Empty, migrated database kept in memory, with the default options.
+    #[cfg(test)]
+    async fn new_in_memory() -> Result<Self, Error> {
+        Self::from(
+            IN_MEMORY_DATABASE,
+            1,
+            Duration::from_secs(5),
+            1000,
+            10,
+            30,
+            false,
+        )
+        .await
+    }
+}
 
-        let id = if let Some(gid) = guild {
-            Some(serenity::GuildId(gid))
+/// Open the SQLite database at `path`, creating it if it doesn't exist yet. With
+/// `IN_MEMORY_DATABASE` it's kept in memory instead, and lost when the pool is closed.
+async fn connect(
+    path: &str,
+    max_connections: u32,
+    busy_timeout: Duration,
+) -> Result<Pool<Sqlite>, Error> {
+    let in_memory = path == IN_MEMORY_DATABASE;
+    let options = if in_memory {
+        SqliteConnectOptions::from_str("sqlite::memory:")?
+    } else {
+        SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+    };
+    let options = options
+        // Already sqlx's default, but tags and votes rely on it to be deleted with their quote, so
+        // don't leave it to chance
+        .foreign_keys(true)
+        // Lets commands read while another one writes, instead of waiting for it
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(busy_timeout);
+    let pool = || {
+        let pool = SqlitePoolOptions::new().max_connections(max_connections);
+        if in_memory {
+            // The database is gone once its last connection closes, and connections sharing it
+            // lock each other out of tables, so keep a single one open for good
+            pool.max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
         } else {
-            None
-        };
-        let database = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect_with(
-                SqliteConnectOptions::new()
-                    .filename(path)
-                    .create_if_missing(true),
-            )
-            .await
-            .expect("Couldn't connect to database"); // TODO handle database creation & connection
-                                                     // errors better
-        let out = Self { database };
-        out.migrate().await;
-        out
+            pool
+        }
+    };
+    // When started at boot the filesystem holding the database might not be mounted yet
+    let mut delay = CONNECT_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match pool().connect_with(options.clone()).await {
+            Ok(database) => return Ok(database),
+            Err(e) if attempt < CONNECT_ATTEMPTS => {
+                warn!(error = %e, "Couldn't connect to database, retrying in {:?}", delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Couldn't connect to database after {} attempts: {}",
+                    attempt, e
+                )
+                .into())
+            }
+        }
     }
 }
 
-type Error = Box<dyn std::error::Error + Send + Sync>;
+/// How many quotes are shown on a single page of paginated output, unless the guild set another
+/// count with /config results-per-page.
+const QUOTES_PER_PAGE: usize = 10;
+/// Most quotes a guild can have shown on a single page.
+const MAX_RESULTS_PER_PAGE: u32 = 25;
+/// How long buttons on interactive replies (pagination, confirmations) stay active.
+const INTERACTION_TIMEOUT: Duration = Duration::from_secs(120);
+/// How many users are shown on the leaderboard.
+const LEADERBOARD_SIZE: i64 = 10;
+/// How long a computed leaderboard is reused before counting quotes again.
+const LEADERBOARD_CACHE_TTL: Duration = Duration::from_secs(60);
+/// How often quotes deleted longer than --keep-deleted-days ago are looked for.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Most quotes /quote random brings up at once.
+const MAX_RANDOM_QUOTES: i64 = 5;
+/// Most characters Discord allows in the content of a message.
+const MESSAGE_LENGTH_LIMIT: usize = 2000;
+/// Characters left free on each page for the page number `paginate` and `send_pages` add.
+const PAGE_HEADER_LENGTH: usize = 40;
+/// How long a channel's recently shown quotes are remembered after the last /quote random in it.
+const RECENT_QUOTES_TTL: Duration = Duration::from_secs(60 * 60);
+/// Path that opens an empty database in memory instead of a file.
+const IN_MEMORY_DATABASE: &str = ":memory:";
+/// How many times connecting to the database is tried before giving up.
+const CONNECT_ATTEMPTS: u32 = 5;
+/// How long to wait before retrying to connect to the database, doubled after every attempt.
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Format of dates in guilds that didn't pick their own, ISO 8601.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+/// Longest date format a guild can pick, in characters.
+const MAX_DATE_FORMAT_LENGTH: usize = 100;
+/// How long cached quote ids are used, which is how long quotes imported while the bot runs can be
+/// missed by /quote random.
+const QUOTE_IDS_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+/// How long members wait between added quotes in guilds that didn't pick their own cooldown.
+const DEFAULT_ADD_COOLDOWN: Duration = Duration::from_secs(5);
+/// Longest cooldown a guild can pick for adding quotes.
+const MAX_ADD_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+/// Most quotes suggested while typing an id, Discord shows no more.
+const AUTOCOMPLETE_CHOICES: i64 = 25;
+/// Longest part of a quote shown in an id suggestion, in characters. Discord allows 100 for the
+/// whole suggestion.
+const AUTOCOMPLETE_QUOTE_LENGTH: usize = 80;
+/// Longest name a quote can be attributed to without a user, Discord's limit for nicknames.
+const MAX_NAME_LENGTH: usize = 32;
+/// How many days it takes for a quote to become half as likely in /quote random's recent mode.
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+/// Lowest weight a quote has in /quote random's recent mode, relative to a brand new one.
+const MIN_RECENCY_WEIGHT: f64 = 0.01;
+/// How many days logged commands are kept, and the longest window /quote usage covers.
+const USAGE_LOG_DAYS: u32 = 90;
+/// How many days /quote usage covers when not told how many.
+const DEFAULT_USAGE_DAYS: u32 = 30;
+/// Most commands /quote usage lists.
+const USAGE_COMMANDS: i64 = 15;
+/// Longest image link a quote can have, in characters.
+const MAX_IMAGE_URL_LENGTH: usize = 2000;
+/// How many messages /quote import-from-channel reads when not told how many.
+const DEFAULT_IMPORT_MESSAGES: u32 = 100;
+/// Most messages /quote import-from-channel reads at once.
+const MAX_IMPORT_MESSAGES: u32 = 1000;
+/// Most messages Discord returns for one request of a channel's history.
+const MESSAGES_PER_REQUEST: u32 = 100;
+/// Longest author a quote added with /quote add-text can have, in characters.
+const MAX_AUTHOR_LENGTH: usize = 100;
+/// Most members offered when a quote is added by a name that's close to theirs, so they fit in one
+/// row of buttons next to the one for keeping the name.
+const NAME_MATCHES: usize = 4;
+/// How many characters from the start of a name members are searched by. Discord only finds
+/// members whose name starts with what's searched, so fewer leave room for typos later in it.
+const MEMBER_SEARCH_PREFIX: usize = 2;
+/// Most members Discord is asked for when looking for ones with a name close to a given one.
+const MEMBER_SEARCH_LIMIT: u64 = 100;
+/// Shortest name that counts as close to every name containing it.
+const MIN_CONTAINED_NAME: usize = 3;
+/// Reply when none of someone's quotes match, unless the guild set its own.
+const DEFAULT_NO_QUOTES_MESSAGE: &str = "No quotes found for user: {user}";
+/// Longest reply for when no quotes match a guild can set, in characters.
+const MAX_NO_QUOTES_MESSAGE_LENGTH: usize = 200;
+/// Printed when the bot is started by hand without a token.
+const NO_TOKEN_HINT: &str = "\
+The bot needs its access token from the Discord developer portal, given in one of these ways:
+  --token <TOKEN>        visible to other users on this machine, fine for a quick try
+  --token-file <PATH>    a file holding only the token
+  DISCORD_TOKEN=<TOKEN>  an environment variable
+To run it as a service, --make-systemd-unit prints a systemd unit reading the token from a file.
+See --help for every option.";
+
+type Error = BotError;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
 #[derive(Debug)]
@@ -81,121 +648,6289 @@ impl std::fmt::Display for DatabaseError {
     }
 }
 
-#[poise::command(slash_command)]
+/// Why a command or maintenance task failed.
+#[derive(Debug)]
+enum BotError {
+    /// A query couldn't be run.
+    Database(sqlx::Error),
+    /// The database couldn't be brought to the current schema.
+    Migration(sqlx::migrate::MigrateError),
+    /// A request to Discord failed, boxed as it's much larger than the others.
+    Discord(Box<serenity::Error>),
+    /// A stored entry couldn't be used.
+    Malformed(DatabaseError),
+    /// JSON couldn't be read or written.
+    Json(serde_json::Error),
+    /// Something given to the bot can't be used, with a message saying why.
+    Invalid(String),
+    /// Any other failure, with a message saying what went wrong.
+    Other(String),
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BotError::Database(e) => Some(e),
+            BotError::Migration(e) => Some(e),
+            BotError::Discord(e) => Some(e.as_ref()),
+            BotError::Malformed(e) => Some(e),
+            BotError::Json(e) => Some(e),
+            BotError::Invalid(_) | BotError::Other(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for BotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BotError::Database(e) => e.fmt(f),
+            BotError::Migration(e) => e.fmt(f),
+            BotError::Discord(e) => e.fmt(f),
+            BotError::Malformed(e) => e.fmt(f),
+            BotError::Json(e) => e.fmt(f),
+            BotError::Invalid(message) | BotError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<sqlx::Error> for BotError {
+    fn from(e: sqlx::Error) -> Self {
+        BotError::Database(e)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for BotError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        BotError::Migration(e)
+    }
+}
+
+impl From<serenity::Error> for BotError {
+    fn from(e: serenity::Error) -> Self {
+        BotError::Discord(Box::new(e))
+    }
+}
+
+impl From<DatabaseError> for BotError {
+    fn from(e: DatabaseError) -> Self {
+        BotError::Malformed(e)
+    }
+}
+
+impl From<serde_json::Error> for BotError {
+    fn from(e: serde_json::Error) -> Self {
+        BotError::Json(e)
+    }
+}
+
+impl From<String> for BotError {
+    fn from(message: String) -> Self {
+        BotError::Other(message)
+    }
+}
+
+/// List every quote by a particular user
+#[poise::command(slash_command, prefix_command, guild_only)]
 async fn list(
     ctx: Context<'_>,
     #[description = "Selected user"] user: serenity::User,
+    #[description = "Only show this page, without buttons"]
+    #[min = 1]
+    page: Option<u32>,
 ) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
     let user_id = user.id.as_u64().to_string();
-    let entries = query!("SELECT * FROM quotes WHERE user_id = ?;", user_id)
-        .fetch_all(&ctx.data().database)
+    let per_page = ctx.data().results_per_page(&guild_id);
+    let (limit, offset) = page_bounds(page, per_page);
+    let entries = query!(
+        r#"SELECT quote as "quote!", quote_date as "quote_date!", created_at,
+        COUNT(*) OVER () as "total!: i64"
+        FROM quotes WHERE user_id = ?1 AND (guild_id = ?2 OR guild_id IS NULL)
+        AND deleted_at IS NULL ORDER BY quote_date LIMIT ?3 OFFSET ?4;"#,
+        user_id,
+        guild_id,
+        limit,
+        offset
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    if entries.is_empty() {
+        let response = match page {
+            Some(page) if page > 1 => format!("There's no page {}", page),
+            _ => ctx.data().no_quotes_message(&guild_id, &[&user.name]),
+        };
+        ctx.send(|f| {
+            f.content(response)
+                .ephemeral(true)
+                .allowed_mentions(|m| m.empty_parse())
+        })
         .await?;
-    let mut response = serenity::MessageBuilder::new();
-    for entry in entries {
-        response
-            .push_bold_safe(entry.quote)
-            .push(" on ")
-            .push(entry.quote_date)
-            .push("\n");
+        return Ok(());
     }
-    ctx.say(response.build()).await?;
-    Ok(())
+    let style = ctx.data().quote_style(&guild_id);
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let mut line = serenity::MessageBuilder::new();
+            push_quote_text(&mut line, &entry.quote, style.code_blocks)
+                .push(" on ")
+                .push_safe(quote_day(&entry.quote_date, entry.created_at, &style))
+                .push("\n");
+            line.build()
+        })
+        .collect();
+    let pages = split_pages(&lines, per_page);
+    send_pages(ctx, &pages, page, entries[0].total, per_page).await
 }
 
-#[poise::command(slash_command, subcommands("add", "random", "list"))]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands(
+        "add",
+        "addname",
+        "add_text",
+        "random",
+        "mine",
+        "notmine",
+        "oldest",
+        "newest",
+        "daily",
+        "onthisday",
+        "get",
+        "list",
+        "delete",
+        "edit",
+        "search",
+        "whosaid",
+        "leaderboard",
+        "top",
+        "on",
+        "tags",
+        "count",
+        "stats",
+        "usage",
+        "optout",
+        "optin",
+        "clear",
+        "restore",
+        "import_from_channel",
+        "pending",
+        "approve",
+        "reject",
+        "help"
+    )
+)]
 async fn quote(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-#[poise::command(slash_command)]
+/// Add a quote by a user, or save a message given by its link or id as a quote by its author
+#[poise::command(slash_command, prefix_command, guild_only)]
 async fn add(
     ctx: Context<'_>,
-    #[description = "Selected user"] user: serenity::User,
-    #[description = "Quote you want to add"] quote: String,
+    #[description = "Selected user"] user: Option<serenity::User>,
+    #[description = "Quote you want to add"] quote: Option<String>,
+    #[description = "Link or id of a message to quote instead, like one you're replying to"]
+    message: Option<serenity::Message>,
+    #[description = "Comma separated tags, like: funny, cursed"] tags: Option<String>,
+    #[description = "Image to show with the quote, like a screenshot of it"] image: Option<
+        serenity::Attachment,
+    >,
+    #[description = "Link to an image to show with the quote instead"] image_url: Option<String>,
 ) -> Result<(), Error> {
-    let date = Utc::now().date_naive();
-    let user_id = user.id.as_u64().to_string();
-    query!(
-        "INSERT INTO quotes (user_id, quote_date, quote) VALUES (?,?,?)",
-        user_id,
-        date,
-        quote,
-    )
-    .execute(&ctx.data().database)
-    .await?;
-    let response = format!("Quote: {}, by {} added!", quote, user.name);
-    ctx.say(response).await?;
-    Ok(())
+    let tags = tags.as_deref().map(parse_tags).unwrap_or_default();
+    let image_url = match (image, image_url) {
+        (Some(image), _) if !is_image(&image) => {
+            ctx.send(|f| f.content("That file isn't an image").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        (Some(image), _) => Some(image.url),
+        (None, Some(image_url)) => {
+            let image_url = image_url.trim().to_string();
+            if let Some(problem) = invalid_image_url(&image_url) {
+                ctx.send(|f| f.content(problem).ephemeral(true)).await?;
+                return Ok(());
+            }
+            Some(image_url)
+        }
+        (None, None) => None,
+    };
+    // Slash commands aren't told which message they were replied to with, so it has to be passed
+    if let Some(message) = message {
+        // Messages are fetched by any link or id the bot can see, not only ones the member can
+        if !can_read_history(ctx, message.channel_id).await? {
+            ctx.send(|f| {
+                f.content("You can only quote messages from channels of this server you can read")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+        save_quote(
+            ctx,
+            Author::User(&message.author),
+            &message.content,
+            &tags,
+            Some(&message),
+            image_url.as_deref(),
+        )
+        .await
+    } else if let (Some(user), Some(quote)) = (user, quote) {
+        save_quote(
+            ctx,
+            Author::User(&user),
+            &quote,
+            &tags,
+            None,
+            image_url.as_deref(),
+        )
+        .await
+    } else {
+        ctx.send(|f| {
+            f.content("Pass a user and their quote, or a message to quote")
+                .ephemeral(true)
+        })
+        .await?;
+        Ok(())
+    }
 }
 
-/// Bring up a random quote by a particular user
-#[poise::command(slash_command)]
-async fn random(
+/// Add a quote by someone given by name, like a person who left the server or was never in it
+///
+/// Members with a name close to the given one are offered to pick from instead.
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn addname(
     ctx: Context<'_>,
-    #[description = "Selected user"] user: serenity::User,
+    #[description = "Name of whoever said it"]
+    #[autocomplete = "autocomplete_member_name"]
+    name: String,
+    #[description = "Quote you want to add"] quote: String,
+    #[description = "Comma separated tags, like: funny, cursed"] tags: Option<String>,
 ) -> Result<(), Error> {
-    let user_id = user.id.as_u64().to_string();
-    let entry = query!(
-        "SELECT * FROM quotes WHERE user_id = ? ORDER BY RANDOM() LIMIT 1;",
-        user_id
-    )
-    .fetch_one(&ctx.data().database)
-    .await;
-    if let Ok(body) = entry {
-        let response = serenity::MessageBuilder::new()
-            .push_bold_safe(body.quote.ok_or(DatabaseError::MalformedEntry)?)
-            .push("\n")
-            .mention(&user)
-            .push(" on ")
-            .push(body.quote_date.ok_or(DatabaseError::MalformedEntry)?)
-            .build();
-        ctx.say(response).await?;
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        let response = format!("Names can be 1 to {} characters long", MAX_NAME_LENGTH);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let tags = tags.as_deref().map(parse_tags).unwrap_or_default();
+    let matches = closest_members(ctx, name).await;
+    let exact: Vec<&serenity::Member> = matches
+        .iter()
+        .filter(|(_, distance)| *distance == 0)
+        .map(|(member, _)| member)
+        .collect();
+    // Only a member going by exactly that name is picked without asking
+    let author = if let [member] = exact[..] {
+        Author::User(&member.user)
+    } else if matches.is_empty() {
+        Author::Named(name)
     } else {
-        let response = format!("No quotes found for user: {} ", user.name);
+        let members: Vec<&serenity::Member> = matches.iter().map(|(member, _)| member).collect();
+        if let Some(author) = pick_author(ctx, name, &members).await? {
+            author
+        } else {
+            return Ok(());
+        }
+    };
+    save_quote(ctx, author, &quote, &tags, None, None).await
+}
+
+/// Add a quote from outside Discord, like a book or a famous person, by its author as written
+#[poise::command(slash_command, prefix_command, guild_only, rename = "add-text")]
+async fn add_text(
+    ctx: Context<'_>,
+    #[description = "Who or what it's from, like: Marcus Aurelius, Meditations"] author: String,
+    #[description = "Quote you want to add"] quote: String,
+    #[description = "Comma separated tags, like: funny, cursed"] tags: Option<String>,
+) -> Result<(), Error> {
+    let author = author.trim();
+    if author.is_empty() || author.chars().count() > MAX_AUTHOR_LENGTH {
+        let response = format!("Authors can be 1 to {} characters long", MAX_AUTHOR_LENGTH);
         ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let tags = tags.as_deref().map(parse_tags).unwrap_or_default();
+    save_quote(ctx, Author::Named(author), &quote, &tags, None, None).await
+}
+
+/// Suggest the names of members close to what was typed so far, after what was typed itself.
+async fn autocomplete_member_name(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.trim();
+    if partial.is_empty() {
+        return Vec::new();
+    }
+    let mut names = vec![partial.to_string()];
+    for (member, _) in closest_members(ctx, partial).await {
+        let name = member.display_name().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Members of the invoking guild whose username or nickname is close to `name`, with their
+/// `name_distance`, closest first. Without the members intent the cache only has the members seen
+/// lately, so Discord is searched as well.
+async fn closest_members(ctx: Context<'_>, name: &str) -> Vec<(serenity::Member, usize)> {
+    let guild = if let Some(guild) = ctx.guild_id() {
+        guild
+    } else {
+        return Vec::new();
     };
-    Ok(())
+    let mut members: Vec<serenity::Member> = ctx
+        .guild()
+        .map(|guild| guild.members.into_values().collect())
+        .unwrap_or_default();
+    let prefix: String = name.chars().take(MEMBER_SEARCH_PREFIX).collect();
+    match guild
+        .search_members(ctx, &prefix, Some(MEMBER_SEARCH_LIMIT))
+        .await
+    {
+        Ok(found) => members.extend(found),
+        Err(e) => warn!(error = %e, "Couldn't search for members"),
+    }
+    let candidates = members.iter().flat_map(|member| {
+        std::iter::once(member.user.name.as_str())
+            .chain(member.nick.as_deref())
+            .map(move |candidate| (member.user.id, candidate))
+    });
+    closest_names(candidates, name)
+        .into_iter()
+        .filter_map(|(id, distance)| {
+            let member = members.iter().find(|member| member.user.id == id)?;
+            Some((member.clone(), distance))
+        })
+        .collect()
 }
 
-#[tokio::main]
-async fn main() {
-    let cli = Cli::parse();
-    if cli.unit {
-        println!("{}", systemd_unit(&cli.token, &cli.database, cli.guild));
-        return;
+/// The `(key, name_distance)` of the `(key, name)` candidates close to `name`, closest first and
+/// at most `NAME_MATCHES` of them. A key with several names counts with its closest one.
+fn closest_names<'a, T: Copy + PartialEq>(
+    candidates: impl IntoIterator<Item = (T, &'a str)>,
+    name: &str,
+) -> Vec<(T, usize)> {
+    let mut closest: Vec<(T, usize)> = Vec::new();
+    for (key, candidate) in candidates {
+        let distance = if let Some(distance) = name_distance(candidate, name) {
+            distance
+        } else {
+            continue;
+        };
+        if let Some(found) = closest.iter_mut().find(|(found, _)| *found == key) {
+            found.1 = found.1.min(distance);
+        } else {
+            closest.push((key, distance));
+        }
     }
-    let data = Data::from(cli.guild, cli.database).await;
-    let framework = poise::Framework::builder()
-        .options(poise::FrameworkOptions {
-            commands: vec![quote()],
-            ..Default::default()
+    closest.sort_by_key(|(_, distance)| *distance);
+    closest.truncate(NAME_MATCHES);
+    closest
+}
+
+/// How far `candidate` is from `name`, ignoring case: 0 if they're the same, 1 if one contains the
+/// other, otherwise one more than the edit distance between them. None if more than a third of
+/// their characters differ, which is more than a typo.
+fn name_distance(candidate: &str, name: &str) -> Option<usize> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    if candidate == name {
+        return Some(0);
+    }
+    let (shorter, longer) = if candidate.len() < name.len() {
+        (&candidate, &name)
+    } else {
+        (&name, &candidate)
+    };
+    if shorter.len() >= MIN_CONTAINED_NAME
+        && longer
+            .windows(shorter.len())
+            .any(|window| window == &shorter[..])
+    {
+        return Some(1);
+    }
+    // Levenshtein distance, with swapping two neighbouring characters as one edit since that's a
+    // common typo
+    let mut distances = vec![vec![0; name.len() + 1]; candidate.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=candidate.len() {
+        for j in 1..=name.len() {
+            let cost = usize::from(candidate[i - 1] != name[j - 1]);
+            let mut distance = (distances[i - 1][j - 1] + cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && candidate[i - 1] == name[j - 2] && candidate[i - 2] == name[j - 1]
+            {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    let distance = distances[candidate.len()][name.len()];
+    (distance * 3 <= longer.len()).then_some(distance + 1)
+}
+
+/// Ask the invoking user whether a quote given by `name` is by one of `members` or by someone with
+/// just that name. None if they don't answer within `INTERACTION_TIMEOUT`.
+async fn pick_author<'a>(
+    ctx: Context<'_>,
+    name: &'a str,
+    members: &[&'a serenity::Member],
+) -> Result<Option<Author<'a>>, Error> {
+    let ctx_id = ctx.id();
+    let named_button_id = format!("{}named", ctx_id);
+    let member_button_id = |index: usize| format!("{}member{}", ctx_id, index);
+    let prompt = serenity::MessageBuilder::new()
+        .push("Is the quote by one of these members, or by someone else called ")
+        .push_safe(escape_markdown(name))
+        .push("?")
+        .build();
+    let reply = ctx
+        .send(|f| {
+            f.content(prompt).ephemeral(true).components(|c| {
+                c.create_action_row(|r| {
+                    for (index, member) in members.iter().enumerate() {
+                        let label = if member.nick.is_some() {
+                            format!("{} ({})", member.display_name(), member.user.name)
+                        } else {
+                            member.user.name.clone()
+                        };
+                        r.create_button(|b| {
+                            b.custom_id(member_button_id(index))
+                                .label(label)
+                                .style(serenity::ButtonStyle::Primary)
+                        });
+                    }
+                    r.create_button(|b| {
+                        b.custom_id(&named_button_id)
+                            .label("Someone else")
+                            .style(serenity::ButtonStyle::Secondary)
+                    })
+                })
+            })
         })
-        .token(cli.token)
-        .intents(serenity::GatewayIntents::non_privileged())
-        .setup(|ctx, _ready, framework| {
-            Box::pin(async move {
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(data)
+        .await?;
+
+    let author_id = ctx.author().id;
+    let press = serenity::CollectComponentInteraction::new(ctx)
+        .filter(move |press| {
+            press.data.custom_id.starts_with(&ctx_id.to_string()) && press.user.id == author_id
+        })
+        .timeout(INTERACTION_TIMEOUT)
+        .await;
+    let author = if let Some(press) = press {
+        press
+            .create_interaction_response(ctx, |r| {
+                r.kind(serenity::InteractionResponseType::DeferredUpdateMessage)
             })
-        });
+            .await?;
+        if press.data.custom_id == named_button_id {
+            Some(Author::Named(name))
+        } else {
+            (0..members.len())
+                .find(|index| press.data.custom_id == member_button_id(*index))
+                .map(|index| Author::User(&members[index].user))
+        }
+    } else {
+        None
+    };
+    let response = if author.is_some() {
+        "Adding the quote"
+    } else {
+        "Nothing was added"
+    };
+    reply
+        .edit(ctx, |f| f.content(response).components(|c| c))
+        .await?;
+    Ok(author)
+}
 
-    framework.run().await.unwrap();
+/// Save a message as a quote by its author
+#[poise::command(context_menu_command = "Quote this message", guild_only)]
+async fn quote_message(
+    ctx: Context<'_>,
+    #[description = "Message to quote"] message: serenity::Message,
+) -> Result<(), Error> {
+    save_quote(
+        ctx,
+        Author::User(&message.author),
+        &message.content,
+        &[],
+        Some(&message),
+        None,
+    )
+    .await
 }
 
-fn systemd_unit(tok: &str, db: &Option<String>, guild: Option<u64>) -> String {
-    let mut unit = format!(
-        r"[Unit]
-Description=Discord quote bot
-[Service]
-ExecStart=/usr/bin/discord_quote_bot --token {}",
-        tok
-    );
-    if let Some(database) = db {
-        unit.push_str(&format!("--database {}", database));
+/// Bring up random quotes by any of the given users, or by anyone if no user is given
+#[poise::command(slash_command, guild_only)]
+#[allow(clippy::too_many_arguments)]
+async fn random(
+    ctx: Context<'_>,
+    #[description = "Selected user"] user: Option<serenity::User>,
+    #[description = "Only pick quotes with this tag"] tag: Option<String>,
+    #[description = "How many quotes to bring up, at most 5"]
+    #[min = 1]
+    #[max = 5]
+    count: Option<i64>,
+    #[description = "Only pick quotes added on or after this day, like 2023-06-01"] after: Option<
+        String,
+    >,
+    #[description = "Only pick quotes added on or before this day, like 2023-08-31"] before: Option<
+        String,
+    >,
+    #[description = "Only pick quotes with at least this score from votes"] min_score: Option<i64>,
+    #[description = "Only pick quotes at least this many characters long"]
+    #[min = 1]
+    min_length: Option<i64>,
+    #[description = "Only pick quotes at most this many characters long"]
+    #[min = 1]
+    max_length: Option<i64>,
+    #[description = "Another user to pick from"] user2: Option<serenity::User>,
+    #[description = "Another user to pick from"] user3: Option<serenity::User>,
+    #[description = "Another user to pick from"] user4: Option<serenity::User>,
+    #[description = "Another user to pick from"] user5: Option<serenity::User>,
+    #[description = "Also pick from quotes by this name, like ones added with /quote addname"]
+    name: Option<String>,
+    #[description = "Pick any quote alike, or recently added ones more often"] mode: Option<
+        RandomMode,
+    >,
+    #[description = "Also show who submitted the quotes and the messages they were saved from"]
+    verbose: Option<bool>,
+) -> Result<(), Error> {
+    let mut users: Vec<serenity::User> = Vec::new();
+    for user in [user, user2, user3, user4, user5].into_iter().flatten() {
+        if !users.iter().any(|added| added.id == user.id) {
+            users.push(user);
+        }
     }
-    if let Some(guild) = guild {
-        unit.push_str(&format!("--guild {}", guild.to_string()));
+    if let (Some(min_length), Some(max_length)) = (min_length, max_length) {
+        if min_length > max_length {
+            ctx.send(|f| {
+                f.content("The shortest length can't be more than the longest")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+    }
+    let after = match after.as_deref().map(parse_day).transpose() {
+        Ok(after) => after,
+        Err(problem) => {
+            ctx.send(|f| f.content(problem).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let before = match before.as_deref().map(parse_day).transpose() {
+        Ok(before) => before,
+        Err(problem) => {
+            ctx.send(|f| f.content(problem).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let user_ids = user_ids(&users);
+    let tag = tag.map(|tag| tag.trim().to_lowercase());
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let filter = QuoteFilter {
+        user_ids: &user_ids,
+        name: name.as_deref(),
+        tag: tag.as_deref(),
+        after,
+        before,
+        min_score,
+        min_length,
+        max_length,
+        excluded_user_id: None,
+    };
+    let options = RandomOptions {
+        count,
+        mode: mode.unwrap_or_default(),
+        verbose: verbose.unwrap_or(false),
+    };
+    send_random_quotes(ctx, &users, filter, options).await
+}
+
+/// Text command version of /quote random, which only takes the users to pick from. Parsing every
+/// option of /quote random from a message makes poise generate more code than rustc can compile.
+#[poise::command(prefix_command, guild_only)]
+async fn random_text(
+    ctx: Context<'_>,
+    #[description = "Users to pick from"] users: Vec<serenity::User>,
+) -> Result<(), Error> {
+    let mut unique: Vec<serenity::User> = Vec::new();
+    for user in users {
+        if !unique.iter().any(|added| added.id == user.id) {
+            unique.push(user);
+        }
+    }
+    let user_ids = user_ids(&unique);
+    let filter = QuoteFilter {
+        user_ids: &user_ids,
+        ..Default::default()
+    };
+    send_random_quotes(ctx, &unique, filter, RandomOptions::default()).await
+}
+
+/// Bring up a random quote of yours
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn mine(ctx: Context<'_>) -> Result<(), Error> {
+    let users = [ctx.author().clone()];
+    let user_ids = user_ids(&users);
+    let filter = QuoteFilter {
+        user_ids: &user_ids,
+        ..Default::default()
+    };
+    send_random_quotes(ctx, &users, filter, RandomOptions::default()).await
+}
+
+/// Bring up a random quote by anyone but you, like when guessing who said it
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn notmine(ctx: Context<'_>) -> Result<(), Error> {
+    let author_id = ctx.author().id.as_u64().to_string();
+    let filter = QuoteFilter {
+        excluded_user_id: Some(&author_id),
+        ..Default::default()
+    };
+    send_random_quotes(ctx, &[], filter, RandomOptions::default()).await
+}
+
+/// Bring up the first quote added, of a particular user or of anyone
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn oldest(
+    ctx: Context<'_>,
+    #[description = "Selected user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    send_edge_quote(ctx, user, false).await
+}
+
+/// Bring up the latest quote added, of a particular user or of anyone
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn newest(
+    ctx: Context<'_>,
+    #[description = "Selected user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    send_edge_quote(ctx, user, true).await
+}
+
+/// Bring up the quote of the day, which stays the same until midnight in the server's timezone
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn daily(
+    ctx: Context<'_>,
+    #[description = "Only pick from this user's quotes"] user: Option<serenity::User>,
+    #[description = "Pick a quote just for you instead of the same one for everyone"]
+    personal: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let style = ctx.data().quote_style(&guild_id);
+    let now = Utc::now();
+    let today = now.with_timezone(&style.timezone).date_naive();
+    // Quotes added today are left out, so adding one doesn't change today's pick
+    let start_of_today = style
+        .timezone
+        .from_local_datetime(&today.and_time(NaiveTime::MIN))
+        .earliest()
+        .map_or(now, |start| start.with_timezone(&Utc));
+    let user_id = user.as_ref().map(|user| user.id.as_u64().to_string());
+    let ids: Vec<i64> = query!(
+        r#"SELECT id as "id!", COALESCE(created_at, quote_date) < ?3 as "before_today!: bool"
+        FROM quotes WHERE (?1 IS NULL OR user_id = ?1)
+        AND (guild_id = ?2 OR guild_id IS NULL) AND deleted_at IS NULL ORDER BY id;"#,
+        user_id,
+        guild_id,
+        start_of_today
+    )
+    .fetch_all(&ctx.data().database)
+    .await?
+    .into_iter()
+    .filter(|entry| entry.before_today)
+    .map(|entry| entry.id)
+    .collect();
+    let personal = personal
+        .unwrap_or(false)
+        .then(|| ctx.author().id.as_u64().to_string());
+    let seed = format!(
+        "{}/{}/{}/{}",
+        guild_id,
+        today,
+        user_id.as_deref().unwrap_or(""),
+        personal.as_deref().unwrap_or("")
+    );
+    let entry = if let Some(id) = daily_pick(&ids, &seed) {
+        query_as!(
+            StoredQuote,
+            r#"SELECT id as "id!", user_id, username, quote as "quote!",
+            quote_date as "quote_date!", created_at, added_by, guild_id, channel_id, message_id,
+            image_url, image_channel_id, image_message_id
+            FROM quotes WHERE id = ?;"#,
+            id
+        )
+        .fetch_optional(&ctx.data().database)
+        .await?
+    } else {
+        None
+    };
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = if let Some(user) = user {
+            ctx.data().no_quotes_message(&guild_id, &[&user.name])
+        } else {
+            "No quotes found that were added before today".to_string()
+        };
+        ctx.send(|f| {
+            f.content(response)
+                .ephemeral(true)
+                .allowed_mentions(|m| m.empty_parse())
+        })
+        .await?;
+        return Ok(());
+    };
+    show_quote(ctx, entry.shown(&style)?).await
+}
+
+/// Bring up quotes added on this day in earlier years
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn onthisday(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let style = ctx.data().quote_style(&guild_id);
+    let today = Utc::now().with_timezone(&style.timezone).date_naive();
+    let entries = anniversary_quotes(&ctx.data().database, &guild_id, today).await?;
+    if entries.is_empty() {
+        ctx.send(|f| {
+            f.content("No quotes were added on this day in earlier years")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let (response, authors) = anniversary_message(&entries, &style)?;
+    ctx.send(|f| {
+        f.content(response)
+            .allowed_mentions(|m| mention_only(m, authors))
+    })
+    .await?;
+    Ok(())
+}
+
+/// Quotes in the guild with id `guild_id` added on the month and day of `today` in earlier years,
+/// oldest first.
+async fn anniversary_quotes(
+    database: &Pool<Sqlite>,
+    guild_id: &str,
+    today: NaiveDate,
+) -> Result<Vec<StoredQuote>, Error> {
+    let month_day = today.format("%m-%d").to_string();
+    let year = today.format("%Y").to_string();
+    let entries = query_as!(
+        StoredQuote,
+        r#"SELECT id as "id!", user_id, username, quote as "quote!",
+        quote_date as "quote_date!", created_at, added_by, guild_id, channel_id, message_id,
+        image_url, image_channel_id, image_message_id
+        FROM quotes WHERE strftime('%m-%d', quote_date) = ?1 AND strftime('%Y', quote_date) < ?2
+        AND (guild_id = ?3 OR guild_id IS NULL) AND deleted_at IS NULL
+        ORDER BY quote_date, id;"#,
+        month_day,
+        year,
+        guild_id
+    )
+    .fetch_all(database)
+    .await?;
+    Ok(entries)
+}
+
+/// A message listing `entries` under an "On this day" heading, with the authors it mentions.
+/// Quotes that don't fit in one message are left out.
+fn anniversary_message(
+    entries: &[StoredQuote],
+    style: &QuoteStyle,
+) -> Result<(String, Vec<serenity::UserId>), Error> {
+    let mut response = "On this day\n".to_string();
+    let mut authors = Vec::new();
+    for entry in entries {
+        let mut line = serenity::MessageBuilder::new();
+        let author = push_quote_line(
+            &mut line,
+            entry.id,
+            entry.user_id.as_deref(),
+            entry.username.as_deref(),
+            &entry.quote,
+            &quote_day(&entry.quote_date, entry.created_at, style),
+            style.code_blocks,
+        )?;
+        let line = line.build();
+        if response.chars().count() + line.chars().count() > MESSAGE_LENGTH_LIMIT {
+            continue;
+        }
+        response.push_str(&line);
+        authors.extend(author);
+    }
+    Ok((response, authors))
+}
+
+/// One of `ids`, always the same one for the same `seed` and `ids`. Uses FNV-1a rather than `rng`
+/// or std's hasher, whose output can change between versions.
+fn daily_pick(ids: &[i64], seed: &str) -> Option<i64> {
+    if ids.is_empty() {
+        return None;
+    }
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in seed.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    Some(ids[(hash % ids.len() as u64) as usize])
+}
+
+/// Respond with the oldest quote of `user`, or of anyone, or with the newest one if `newest`.
+async fn send_edge_quote(
+    ctx: Context<'_>,
+    user: Option<serenity::User>,
+    newest: bool,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let user_id = user.as_ref().map(|user| user.id.as_u64().to_string());
+    // Quotes added on the same day are told apart by when they were added, older quotes without
+    // that by their id
+    let entry = if newest {
+        query_as!(
+            StoredQuote,
+            r#"SELECT id as "id!", user_id, username, quote as "quote!",
+            quote_date as "quote_date!", created_at, added_by, guild_id, channel_id, message_id,
+            image_url, image_channel_id, image_message_id
+            FROM quotes WHERE (?1 IS NULL OR user_id = ?1)
+            AND (guild_id = ?2 OR guild_id IS NULL) AND deleted_at IS NULL
+            ORDER BY quote_date DESC, created_at DESC, id DESC LIMIT 1;"#,
+            user_id,
+            guild_id
+        )
+        .fetch_optional(&ctx.data().database)
+        .await?
+    } else {
+        query_as!(
+            StoredQuote,
+            r#"SELECT id as "id!", user_id, username, quote as "quote!",
+            quote_date as "quote_date!", created_at, added_by, guild_id, channel_id, message_id,
+            image_url, image_channel_id, image_message_id
+            FROM quotes WHERE (?1 IS NULL OR user_id = ?1)
+            AND (guild_id = ?2 OR guild_id IS NULL) AND deleted_at IS NULL
+            ORDER BY quote_date, created_at, id LIMIT 1;"#,
+            user_id,
+            guild_id
+        )
+        .fetch_optional(&ctx.data().database)
+        .await?
+    };
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = if let Some(user) = user {
+            ctx.data().no_quotes_message(&guild_id, &[&user.name])
+        } else {
+            "No quotes found".to_string()
+        };
+        ctx.send(|f| {
+            f.content(response)
+                .ephemeral(true)
+                .allowed_mentions(|m| m.empty_parse())
+        })
+        .await?;
+        return Ok(());
+    };
+    show_quote(ctx, entry.shown(&ctx.data().quote_style(&guild_id))?).await
+}
+
+/// Ids of `users`, as they're stored.
+fn user_ids(users: &[serenity::User]) -> Vec<String> {
+    users
+        .iter()
+        .map(|user| user.id.as_u64().to_string())
+        .collect()
+}
+
+/// How many quotes /quote random picks and how.
+#[derive(Default)]
+struct RandomOptions {
+    /// One if not given, and at most as many as fit on a page.
+    count: Option<i64>,
+    mode: RandomMode,
+    /// Whether to also show who submitted the quotes and the messages they were saved from.
+    verbose: bool,
+}
+
+/// Respond with random quotes matching `filter`, `users` being the users whose ids it has.
+async fn send_random_quotes(
+    ctx: Context<'_>,
+    users: &[serenity::User],
+    filter: QuoteFilter<'_>,
+    options: RandomOptions,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let most = MAX_RANDOM_QUOTES.min(ctx.data().results_per_page(&guild_id) as i64);
+    let count = options.count.unwrap_or(1).clamp(1, most);
+    let entries = random_quotes(
+        ctx.data(),
+        &guild_id,
+        ctx.channel_id(),
+        &filter,
+        count,
+        options.mode,
+    )
+    .await?;
+    let style = ctx.data().quote_style(&guild_id);
+    if entries.is_empty() {
+        let names: Vec<&str> = users
+            .iter()
+            .map(|user| user.name.as_str())
+            .chain(filter.name)
+            .collect();
+        let only_own = if let Some(excluded) = filter.excluded_user_id {
+            !ctx.data()
+                .quote_ids(&guild_id, Some(excluded))
+                .await?
+                .is_empty()
+        } else {
+            false
+        };
+        let mut response = match &names[..] {
+            _ if only_own => "Only your own quotes match".to_string(),
+            [] if filter.excluded_user_id.is_some() => {
+                "No quotes found by anyone but you".to_string()
+            }
+            [] => "No quotes found".to_string(),
+            names => ctx.data().no_quotes_message(&guild_id, names),
+        };
+        if let Some(tag) = filter.tag {
+            response.push_str(&format!(" tagged {}", tag));
+        }
+        if let Some(after) = filter.after {
+            response.push_str(&format!(" from {}", after));
+        }
+        if let Some(before) = filter.before {
+            response.push_str(&format!(" until {}", before));
+        }
+        if let Some(min_score) = filter.min_score {
+            response.push_str(&format!(" with a score of at least {}", min_score));
+        }
+        match (filter.min_length, filter.max_length) {
+            (Some(min_length), Some(max_length)) => response.push_str(&format!(
+                " between {} and {} characters long",
+                min_length, max_length
+            )),
+            (Some(min_length), None) => {
+                response.push_str(&format!(" at least {} characters long", min_length))
+            }
+            (None, Some(max_length)) => {
+                response.push_str(&format!(" at most {} characters long", max_length))
+            }
+            (None, None) => {}
+        }
+        ctx.send(|f| {
+            f.content(response)
+                .ephemeral(true)
+                .allowed_mentions(|m| m.empty_parse())
+        })
+        .await?;
+        return Ok(());
+    }
+
+    if let [body] = &entries[..] {
+        ctx.data().remember_recent_quote(ctx.channel_id(), body.id);
+        let mut shown = body.shown(&style)?;
+        if !options.verbose {
+            shown.added_by = None;
+            shown.link = None;
+        }
+        show_quote(ctx, shown).await?;
+        return Ok(());
+    }
+
+    let mut response = String::new();
+    let mut authors = Vec::new();
+    for body in entries {
+        let mut line = serenity::MessageBuilder::new();
+        let author = push_quote_line(
+            &mut line,
+            body.id,
+            body.user_id.as_deref(),
+            body.username.as_deref(),
+            &body.quote,
+            &quote_day(&body.quote_date, body.created_at, &style),
+            style.code_blocks,
+        )?;
+        if options.verbose {
+            let shown = body.shown(&style)?;
+            if let Some(added_by) = shown.added_by {
+                line.push("    submitted by ").mention(&added_by).push("\n");
+            }
+            if let Some(link) = shown.link {
+                line.push(format!("    <{}>\n", link));
+            }
+        }
+        let line = line.build();
+        // Long quotes that don't fit in one message are left out, and can come up another time
+        if response.chars().count() + line.chars().count() > MESSAGE_LENGTH_LIMIT {
+            continue;
+        }
+        response.push_str(&line);
+        authors.extend(author);
+        ctx.data().remember_recent_quote(ctx.channel_id(), body.id);
+    }
+    if response.is_empty() {
+        ctx.send(|f| {
+            f.content("Those quotes are too long to show together, try a smaller count")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    ctx.send(|f| {
+        f.content(response)
+            .allowed_mentions(|m| mention_only(m, authors))
+    })
+    .await?;
+    Ok(())
+}
+
+/// Which quotes /quote random picks from, unset filters match every quote.
+#[derive(Default)]
+struct QuoteFilter<'a> {
+    /// Users whose quotes can be picked, anyone's when empty and without a `name`.
+    user_ids: &'a [String],
+    /// Name, compared ignoring case, whose quotes can be picked too. Matches the name stored with
+    /// quotes, both of people given only by name and of users when they were quoted.
+    name: Option<&'a str>,
+    tag: Option<&'a str>,
+    /// First day quotes could have been added on.
+    after: Option<NaiveDate>,
+    /// Last day quotes could have been added on.
+    before: Option<NaiveDate>,
+    /// Lowest sum of votes quotes could have.
+    min_score: Option<i64>,
+    /// Fewest characters quotes could have. SQLite's LENGTH counts characters like `str::chars`,
+    /// so an emoji made of several code points counts as several.
+    min_length: Option<i64>,
+    /// Most characters quotes could have, counted like `min_length`.
+    max_length: Option<i64>,
+    /// User whose quotes are left out, even when they're in `user_ids`.
+    excluded_user_id: Option<&'a str>,
+}
+
+/// How /quote random picks among the quotes that match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, poise::ChoiceParameter)]
+enum RandomMode {
+    /// Every quote is as likely as any other.
+    #[default]
+    #[name = "uniform"]
+    Uniform,
+    /// Recently added quotes are more likely, as weighed by `recency_weight`.
+    #[name = "recent"]
+    Recent,
+}
+
+/// How likely a quote added `age_days` days ago is picked in `RandomMode::Recent`, compared to one
+/// added just now. Halves every `RECENCY_HALF_LIFE_DAYS`, but never drops below
+/// `MIN_RECENCY_WEIGHT` so old quotes still come up now and then.
+fn recency_weight(age_days: f64) -> f64 {
+    0.5_f64
+        .powf(age_days.max(0.0) / RECENCY_HALF_LIFE_DAYS)
+        .max(MIN_RECENCY_WEIGHT)
+}
+
+/// Up to `count` random quotes in the guild with id `guild_id` that match `filter`, picked as
+/// `mode` says, leaving out the ones recently shown in `channel` unless every match was.
+async fn random_quotes(
+    data: &Data,
+    guild_id: &str,
+    channel: serenity::ChannelId,
+    filter: &QuoteFilter<'_>,
+    count: i64,
+    mode: RandomMode,
+) -> Result<Vec<StoredQuote>, Error> {
+    // Without filters the ids can come from the cache, SQLite only has to look up the picked ones
+    let candidates = if filter.name.is_none()
+        && filter.tag.is_none()
+        && filter.after.is_none()
+        && filter.before.is_none()
+        && filter.min_score.is_none()
+        && filter.min_length.is_none()
+        && filter.max_length.is_none()
+        && filter.excluded_user_id.is_none()
+    {
+        if filter.user_ids.is_empty() {
+            data.quote_ids(guild_id, None).await?
+        } else {
+            let mut ids = Vec::new();
+            for user_id in filter.user_ids {
+                ids.extend(data.quote_ids(guild_id, Some(user_id)).await?.iter());
+            }
+            Arc::new(ids)
+        }
+    } else {
+        let user_ids = serde_json::to_string(filter.user_ids)?;
+        // Quotes by someone given only by name are never left out, they have no user id. Written
+        // without an OR, which would make checking the query at compile time take much longer.
+        let ids = query!(
+            "SELECT quotes.id FROM quotes LEFT JOIN votes ON votes.quote_id = quotes.id
+            WHERE (?1 = '[]' AND ?7 IS NULL OR user_id IN (SELECT value FROM json_each(?1))
+            OR username = ?7 COLLATE NOCASE)
+            AND (guild_id = ?2 OR guild_id IS NULL)
+            AND (?3 IS NULL OR quotes.id IN (SELECT quote_id FROM tags WHERE tag = ?3))
+            AND deleted_at IS NULL
+            AND (?4 IS NULL OR quote_date >= ?4)
+            AND (?5 IS NULL OR quote_date <= ?5)
+            AND (?8 IS NULL OR LENGTH(quote) >= ?8)
+            AND (?9 IS NULL OR LENGTH(quote) <= ?9)
+            AND COALESCE(user_id, '') IS NOT ?10
+            GROUP BY quotes.id HAVING ?6 IS NULL OR COALESCE(SUM(vote), 0) >= ?6;",
+            user_ids,
+            guild_id,
+            filter.tag,
+            filter.after,
+            filter.before,
+            filter.min_score,
+            filter.name,
+            filter.min_length,
+            filter.max_length,
+            filter.excluded_user_id
+        )
+        .fetch_all(&data.database)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Couldn't fetch a random quote");
+            e
+        })?
+        .into_iter()
+        .map(|entry| entry.id)
+        .collect();
+        Arc::new(ids)
+    };
+    let allowed = |recent: &[i64]| -> Vec<i64> {
+        candidates
+            .iter()
+            .copied()
+            .filter(|id| !recent.contains(id))
+            .collect()
+    };
+    let mut allowed_ids = allowed(&data.recent_quotes(channel));
+    if allowed_ids.is_empty() {
+        // Every matching quote was shown recently, start over
+        data.forget_recent_quotes(channel);
+        allowed_ids = allowed(&[]);
+    }
+    let ids: Vec<i64> = if mode == RandomMode::Recent {
+        // Older quotes only have the day they were added on
+        let json = serde_json::to_string(&allowed_ids)?;
+        let weights: HashMap<i64, f64> = query!(
+            r#"SELECT quotes.id as "id!",
+            julianday('now') - julianday(COALESCE(created_at, quote_date)) as "age: f64"
+            FROM json_each(?) JOIN quotes ON quotes.id = json_each.value;"#,
+            json
+        )
+        .fetch_all(&data.database)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.id, recency_weight(entry.age.unwrap_or(0.0))))
+        .collect();
+        let mut rng = data.rng.lock().unwrap();
+        allowed_ids
+            .choose_multiple_weighted(&mut *rng, count as usize, |id| {
+                weights.get(id).copied().unwrap_or(MIN_RECENCY_WEIGHT)
+            })
+            .map(|ids| ids.copied().collect())
+            .unwrap_or_default()
+    } else {
+        let mut rng = data.rng.lock().unwrap();
+        allowed_ids
+            .choose_multiple(&mut *rng, count as usize)
+            .copied()
+            .collect()
+    };
+    let ids = serde_json::to_string(&ids)?;
+    let entries = query_as!(
+        StoredQuote,
+        r#"SELECT quotes.id as "id!", user_id, username, quote as "quote!",
+        quote_date as "quote_date!", created_at, added_by, guild_id, channel_id, message_id,
+        image_url, image_channel_id, image_message_id
+        FROM json_each(?) JOIN quotes ON quotes.id = json_each.value
+        WHERE deleted_at IS NULL ORDER BY json_each.key;"#,
+        ids
+    )
+    .fetch_all(&data.database)
+    .await?;
+    Ok(entries)
+}
+
+/// List the quotes waiting for approval
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn pending(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let entries = query!(
+        "SELECT id, user_id, username, quote, added_by FROM pending_quotes WHERE guild_id = ?
+        ORDER BY id;",
+        guild_id
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    if entries.is_empty() {
+        ctx.send(|f| {
+            f.content("No quotes are waiting for approval")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let code_blocks = ctx.data().quote_style(&guild_id).code_blocks;
+    let mut lines = Vec::new();
+    for entry in &entries {
+        let author = parse_author(entry.user_id.as_deref())?;
+        let added_by = entry
+            .added_by
+            .parse()
+            .map_err(|_| DatabaseError::MalformedEntry)?;
+        let mut line = serenity::MessageBuilder::new();
+        line.push(format!("#{} ", entry.id));
+        push_quote_text(&mut line, &entry.quote, code_blocks).push(" by ");
+        if let Some(author) = author {
+            line.mention(&author);
+        } else {
+            line.push_safe(escape_markdown(&entry.username));
+        }
+        line.push(", submitted by ")
+            .mention(&serenity::UserId(added_by))
+            .push("\n");
+        lines.push(line.build());
+    }
+    let pages = split_pages(&lines, ctx.data().results_per_page(&guild_id));
+    paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// Save a quote that's waiting for approval
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn approve(
+    ctx: Context<'_>,
+    #[description = "Id of the pending quote"] id: i64,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let mut transaction = ctx.data().database.begin().await?;
+    let entry = query!(
+        r#"DELETE FROM pending_quotes WHERE id = ? AND guild_id = ? RETURNING user_id,
+        username as "username!", quote as "quote!", tags as "tags!", added_by as "added_by!",
+        channel_id, message_id, image_url, image_channel_id, image_message_id;"#,
+        id,
+        guild_id
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = format!("No pending quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    };
+    let tags: Vec<String> =
+        serde_json::from_str(&entry.tags).map_err(|_| DatabaseError::MalformedEntry)?;
+    let source = entry.channel_id.as_deref().zip(entry.message_id.as_deref());
+    let image = entry.image_url.as_deref().map(|url| QuoteImage {
+        url,
+        message: entry
+            .image_channel_id
+            .as_deref()
+            .zip(entry.image_message_id.as_deref()),
+    });
+    let insertion = insert_quote(
+        &mut transaction,
+        &guild_id,
+        entry.user_id.as_deref(),
+        &entry.username,
+        &entry.quote,
+        Some(&entry.added_by),
+        source,
+        image,
+    )
+    .await?;
+    let response = match insertion {
+        Insertion::Added(quote_id) => {
+            for tag in &tags {
+                query!(
+                    "INSERT INTO tags (quote_id, tag) VALUES (?,?);",
+                    quote_id,
+                    tag
+                )
+                .execute(&mut transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            ctx.data().forget_quote_ids();
+            let mut notice = serenity::MessageBuilder::new();
+            notice.push("Your quote ");
+            push_quote_text(
+                &mut notice,
+                &entry.quote,
+                ctx.data().quote_style(&guild_id).code_blocks,
+            )
+            .push(format!(" was approved as quote #{}", quote_id));
+            let notice = notice.build();
+            notify_submitter(ctx, &entry.added_by, notice).await;
+            let response = format!("Approved pending quote #{} as quote #{}", id, quote_id);
+            // Uploaded images are only posted again now, a pending quote's reply is only seen by
+            // whoever submitted it
+            return reply_with_image(ctx, quote_id, response, None, image).await;
+        }
+        Insertion::Duplicate(existing) => {
+            // Nothing is left to approve, so the pending quote is still removed
+            transaction.commit().await?;
+            format!(
+                "Pending quote #{} already exists as quote #{}, removed it",
+                id, existing
+            )
+        }
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Discard a quote that's waiting for approval
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn reject(
+    ctx: Context<'_>,
+    #[description = "Id of the pending quote"] id: i64,
+    #[description = "Why it was rejected, told to whoever submitted it"] reason: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let entry = query!(
+        r#"DELETE FROM pending_quotes WHERE id = ? AND guild_id = ?
+        RETURNING quote as "quote!", added_by as "added_by!";"#,
+        id,
+        guild_id
+    )
+    .fetch_optional(&ctx.data().database)
+    .await?;
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = format!("No pending quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    };
+    let mut notice = serenity::MessageBuilder::new();
+    notice.push("Your quote ");
+    push_quote_text(
+        &mut notice,
+        &entry.quote,
+        ctx.data().quote_style(&guild_id).code_blocks,
+    )
+    .push(" was rejected");
+    if let Some(reason) = &reason {
+        notice.push(": ").push_safe(reason);
+    }
+    notify_submitter(ctx, &entry.added_by, notice.build()).await;
+    ctx.say(format!("Rejected pending quote #{}", id)).await?;
+    Ok(())
+}
+
+/// Suggest the newest quotes whose id starts with, or whose text contains, what was typed so far,
+/// by the user already entered in the `user` argument if there is one.
+async fn autocomplete_quote_id(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<poise::AutocompleteChoice<i64>> {
+    suggest_quote_ids(ctx, partial, true).await
+}
+
+/// Like `autocomplete_quote_id`, but only for quotes stored in this guild, as the commands changing
+/// quotes leave those from before guilds were stored alone.
+async fn autocomplete_guild_quote_id(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<poise::AutocompleteChoice<i64>> {
+    suggest_quote_ids(ctx, partial, false).await
+}
+
+/// Quotes to suggest for what was typed so far, see `autocomplete_quote_id`. Those without a guild
+/// are only included if `without_guild`.
+async fn suggest_quote_ids(
+    ctx: Context<'_>,
+    partial: &str,
+    without_guild: bool,
+) -> Vec<poise::AutocompleteChoice<i64>> {
+    let guild_id = if let Some(guild_id) = ctx.guild_id() {
+        guild_id.as_u64().to_string()
+    } else {
+        return Vec::new();
+    };
+    let partial = partial.trim();
+    let user_id = entered_user(ctx);
+    let entries = query!(
+        r#"SELECT id as "id!", quote as "quote!" FROM quotes
+        WHERE (guild_id = ?1 OR guild_id IS NULL AND ?5) AND deleted_at IS NULL
+        AND (instr(CAST(id AS TEXT), ?2) = 1 OR instr(lower(quote), lower(?2)) > 0)
+        AND COALESCE(user_id, '') = COALESCE(?4, user_id, '')
+        ORDER BY id DESC LIMIT ?3;"#,
+        guild_id,
+        partial,
+        AUTOCOMPLETE_CHOICES,
+        user_id,
+        without_guild
+    )
+    .fetch_all(&ctx.data().database)
+    .await;
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!(error = %e, "Couldn't suggest quote ids");
+            return Vec::new();
+        }
+    };
+    entries
+        .into_iter()
+        .map(|entry| poise::AutocompleteChoice {
+            name: format!("{} - {}", entry.id, truncate_quote(&entry.quote)),
+            value: entry.id,
+        })
+        .collect()
+}
+
+/// Id of the user given in the `user` argument of the slash command being autocompleted, which
+/// Discord sends along with the argument being typed.
+fn entered_user(ctx: Context<'_>) -> Option<String> {
+    match ctx {
+        poise::Context::Application(ctx) => {
+            let option = ctx.args.iter().find(|option| option.name == "user")?;
+            option.value.as_ref()?.as_str().map(str::to_string)
+        }
+        poise::Context::Prefix(_) => None,
+    }
+}
+
+/// Whether a quote by `user_id` was written by `user`, or no user was asked for.
+fn quote_by(user: &Option<serenity::User>, user_id: Option<&str>) -> bool {
+    match user {
+        Some(user) => user_id == Some(user.id.as_u64().to_string().as_str()),
+        None => true,
+    }
+}
+
+/// The start of `quote`, on one line, cut to fit in an id suggestion.
+fn truncate_quote(quote: &str) -> String {
+    let quote = quote.split_whitespace().collect::<Vec<_>>().join(" ");
+    if quote.chars().count() <= AUTOCOMPLETE_QUOTE_LENGTH {
+        quote
+    } else {
+        let mut short: String = quote.chars().take(AUTOCOMPLETE_QUOTE_LENGTH - 1).collect();
+        short.push('…');
+        short
+    }
+}
+
+/// Bring up a quote by its id
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn get(
+    ctx: Context<'_>,
+    #[description = "Id of the quote you want to see"]
+    #[autocomplete = "autocomplete_quote_id"]
+    id: i64,
+    #[description = "Only suggest and show quotes by this user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let entry = query_as!(
+        StoredQuote,
+        "SELECT id, user_id, username, quote, quote_date, created_at, added_by, guild_id, channel_id,
+        message_id, image_url, image_channel_id, image_message_id FROM quotes
+        WHERE id = ? AND (guild_id = ? OR guild_id IS NULL) AND deleted_at IS NULL;",
+        id,
+        guild_id
+    )
+    .fetch_optional(&ctx.data().database)
+    .await?
+    .filter(|entry| quote_by(&user, entry.user_id.as_deref()));
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = format!("No quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    };
+    show_quote(ctx, entry.shown(&ctx.data().quote_style(&guild_id))?).await
+}
+
+/// Delete a quote by its id
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn delete(
+    ctx: Context<'_>,
+    #[description = "Id of the quote you want to delete"]
+    #[autocomplete = "autocomplete_guild_quote_id"]
+    id: i64,
+    #[description = "Only suggest and delete quotes by this user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    // Quotes from before guilds were stored are shown in every guild, but none of them owns them
+    // enough to change them
+    let entry = query!(
+        "SELECT * FROM quotes WHERE id = ? AND guild_id = ? AND deleted_at IS NULL;",
+        id,
+        guild_id
+    )
+    .fetch_optional(&ctx.data().database)
+    .await?
+    .filter(|entry| quote_by(&user, entry.user_id.as_deref()));
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = format!("No quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    };
+    let style = ctx.data().quote_style(&guild_id);
+    let mut prompt = serenity::MessageBuilder::new();
+    prompt.push(format!("Delete quote #{}?\n", id));
+    push_quote_text(&mut prompt, &entry.quote, style.code_blocks)
+        .push(" on ")
+        .push_safe(quote_day(&entry.quote_date, entry.created_at, &style));
+    let prompt = prompt.build();
+    let (confirmed, reply) = confirm(ctx, prompt).await?;
+    let response = if confirmed {
+        let now = Utc::now();
+        let deleted = query!(
+            "UPDATE quotes SET deleted_at = ? WHERE id = ? AND guild_id = ? AND deleted_at IS NULL;",
+            now,
+            id,
+            guild_id
+        )
+        .execute(&ctx.data().database)
+        .await?
+        .rows_affected();
+        if deleted == 0 {
+            format!("No quote with id {} found", id)
+        } else {
+            ctx.data().forget_quote_ids();
+            format!(
+                "Deleted quote #{}, it can be brought back with /quote restore for {} day(s)",
+                id,
+                ctx.data().keep_deleted_days
+            )
+        }
+    } else {
+        "Nothing was deleted".to_string()
+    };
+    reply
+        .edit(ctx, |f| f.content(response).components(|c| c))
+        .await?;
+    Ok(())
+}
+
+/// Delete every quote of a user in this server
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn clear(
+    ctx: Context<'_>,
+    #[description = "User whose quotes to delete"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let user_id = user.id.as_u64().to_string();
+    let stored = query!(
+        r#"SELECT COUNT(*) as "c!: i64" FROM quotes
+        WHERE user_id = ? AND guild_id = ? AND deleted_at IS NULL;"#,
+        user_id,
+        guild_id
+    )
+    .fetch_one(&ctx.data().database)
+    .await?
+    .c;
+    if stored == 0 {
+        let response = ctx.data().no_quotes_message(&guild_id, &[&user.name]);
+        ctx.send(|f| {
+            f.content(response)
+                .ephemeral(true)
+                .allowed_mentions(|m| m.empty_parse())
+        })
+        .await?;
+        return Ok(());
+    }
+    let prompt = serenity::MessageBuilder::new()
+        .push(format!("Delete all {} quote(s) by ", stored))
+        .push_safe(&user.name)
+        .push("?")
+        .build();
+    let (confirmed, reply) = confirm(ctx, prompt).await?;
+    let response = if confirmed {
+        let now = Utc::now();
+        let deleted = query!(
+            "UPDATE quotes SET deleted_at = ? WHERE user_id = ? AND guild_id = ?
+            AND deleted_at IS NULL;",
+            now,
+            user_id,
+            guild_id
+        )
+        .execute(&ctx.data().database)
+        .await?
+        .rows_affected();
+        ctx.data().forget_quote_ids();
+        format!(
+            "Deleted {} quote(s), they can be brought back one by one with /quote restore for {} \
+            day(s)",
+            deleted,
+            ctx.data().keep_deleted_days
+        )
+    } else {
+        "Nothing was deleted".to_string()
+    };
+    reply
+        .edit(ctx, |f| f.content(response).components(|c| c))
+        .await?;
+    Ok(())
+}
+
+/// Bring back a deleted quote by its id
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn restore(
+    ctx: Context<'_>,
+    #[description = "Id of the quote you want to restore"] id: i64,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let restored = query!(
+        "UPDATE quotes SET deleted_at = NULL WHERE id = ? AND guild_id = ?
+        AND deleted_at IS NOT NULL;",
+        id,
+        guild_id
+    )
+    .execute(&ctx.data().database)
+    .await?
+    .rows_affected();
+    if restored == 0 {
+        let response = format!("No deleted quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+    } else {
+        ctx.data().forget_quote_ids();
+        ctx.say(format!("Restored quote #{}", id)).await?;
+    }
+    Ok(())
+}
+
+/// Save the recent messages of a channel, like an existing quotes channel, as quotes by their authors
+///
+/// Messages that are already stored, invalid as quotes or by users that opted out are skipped.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    check = "is_moderator",
+    rename = "import-from-channel"
+)]
+async fn import_from_channel(
+    ctx: Context<'_>,
+    #[description = "Channel to read the messages of"] channel: serenity::GuildChannel,
+    #[description = "How many of the latest messages to read, 100 if not given"]
+    #[min = 1]
+    #[max = 1000]
+    limit: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    if !can_read_history(ctx, channel.id).await? {
+        ctx.send(|f| {
+            f.content("Pick a channel of this server you can read")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let limit = limit
+        .unwrap_or(DEFAULT_IMPORT_MESSAGES)
+        .clamp(1, MAX_IMPORT_MESSAGES);
+    // Reading a long history takes longer than Discord waits for a response
+    ctx.defer_ephemeral().await?;
+    let mut messages: Vec<serenity::Message> = Vec::new();
+    while messages.len() < limit as usize {
+        let wanted = (limit as usize - messages.len()).min(MESSAGES_PER_REQUEST as usize);
+        let before = messages.last().map(|message| message.id);
+        let batch = channel
+            .id
+            .messages(ctx, |retriever| {
+                if let Some(before) = before {
+                    retriever.before(before);
+                }
+                retriever.limit(wanted as u64)
+            })
+            .await?;
+        let done = batch.len() < wanted;
+        messages.extend(batch);
+        if done {
+            break;
+        }
+    }
+    let read = messages.len();
+
+    let channel_id = channel.id.as_u64().to_string();
+    let added_by = ctx.author().id.as_u64().to_string();
+    // Looked up before writing anything, so the import doesn't hold the database's write lock
+    // while waiting on Discord. The name of each author, or None if they opted out.
+    let mut authors: HashMap<serenity::UserId, Option<String>> = HashMap::new();
+    let mut imports = Vec::new();
+    // Oldest first, so ids follow the history
+    for message in messages.iter().rev() {
+        let content = message.content.trim();
+        if message.author.bot
+            || !matches!(
+                message.kind,
+                serenity::MessageType::Regular | serenity::MessageType::InlineReply
+            )
+            || invalid_quote(ctx.data(), &guild_id, content).is_some()
+        {
+            continue;
+        }
+        let user_id = message.author.id.as_u64().to_string();
+        let username = if let Some(username) = authors.get(&message.author.id) {
+            username.clone()
+        } else {
+            let username = if opted_out(&ctx.data().database, &user_id).await? {
+                None
+            } else {
+                Some(display_name(ctx, &message.author, channel.guild_id).await)
+            };
+            authors.insert(message.author.id, username.clone());
+            username
+        };
+        let username = if let Some(username) = username {
+            username
+        } else {
+            continue;
+        };
+        imports.push(ImportedMessage {
+            user_id,
+            username,
+            content: content.to_string(),
+            message_id: message.id.as_u64().to_string(),
+            sent: Utc
+                .timestamp_opt(message.timestamp.unix_timestamp(), 0)
+                .single(),
+            image_url: message_image(message).map(str::to_string),
+        });
+    }
+    // Everything is imported at once or not at all
+    let mut transaction = ctx.data().database.begin().await?;
+    let imported = import_messages(
+        &mut transaction,
+        &guild_id,
+        &channel_id,
+        &added_by,
+        &imports,
+    )
+    .await?;
+    transaction.commit().await?;
+    ctx.data().forget_quote_ids();
+    let response = serenity::MessageBuilder::new()
+        .push(format!("Imported {} quote(s) from ", imported))
+        .channel(channel.id)
+        .push(format!(
+            ", skipped {} of the {} message(s) read",
+            read - imported,
+            read
+        ))
+        .build();
+    ctx.send(|f| f.content(response).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Message to be saved as a quote by its author with /quote import-from-channel.
+struct ImportedMessage {
+    user_id: String,
+    /// Name of the author in the guild.
+    username: String,
+    content: String,
+    message_id: String,
+    /// When the message was sent, which the quote is dated by.
+    sent: Option<DateTime<Utc>>,
+    image_url: Option<String>,
+}
+
+/// Store `messages` of the channel with id `channel_id` as quotes, in the order given so ids follow
+/// the history, skipping ones that are already stored. Returns how many were added.
+async fn import_messages(
+    connection: &mut SqliteConnection,
+    guild_id: &str,
+    channel_id: &str,
+    added_by: &str,
+    messages: &[ImportedMessage],
+) -> Result<usize, Error> {
+    let mut imported = 0;
+    for message in messages {
+        let insertion = insert_quote(
+            &mut *connection,
+            guild_id,
+            Some(&message.user_id),
+            &message.username,
+            &message.content,
+            Some(added_by),
+            Some((channel_id, &message.message_id)),
+            message.image_url.as_deref().map(|url| QuoteImage {
+                url,
+                message: Some((channel_id, &message.message_id)),
+            }),
+        )
+        .await?;
+        if let Insertion::Added(id) = insertion {
+            // Dated when the message was sent, not when it was imported
+            if let Some(sent) = message.sent {
+                let date = sent.date_naive();
+                query!(
+                    "UPDATE quotes SET quote_date = ?, created_at = ? WHERE id = ?;",
+                    date,
+                    sent,
+                    id
+                )
+                .execute(&mut *connection)
+                .await?;
+            }
+            imported += 1;
+        }
+    }
+    Ok(imported)
+}
+
+/// Replace the text of an existing quote, keeping its author and date
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn edit(
+    ctx: Context<'_>,
+    #[description = "Id of the quote you want to edit"]
+    #[autocomplete = "autocomplete_guild_quote_id"]
+    id: i64,
+    #[description = "New text of the quote"]
+    #[rest]
+    quote: String,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    if let Some(problem) = invalid_quote(ctx.data(), &guild_id, &quote) {
+        ctx.send(|f| f.content(problem).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let entry = query!(
+        "SELECT * FROM quotes WHERE id = ? AND guild_id = ? AND deleted_at IS NULL;",
+        id,
+        guild_id
+    )
+    .fetch_optional(&ctx.data().database)
+    .await?;
+    let entry = if let Some(entry) = entry {
+        entry
+    } else {
+        let response = format!("No quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    };
+    // The same quote can only be stored once, like when adding it. A deleted copy is replaced, as
+    // it would keep the text from being changed.
+    let mut transaction = ctx.data().database.begin().await?;
+    query!(
+        "DELETE FROM quotes WHERE deleted_at IS NOT NULL AND guild_id = ?1
+        AND (user_id = ?2 OR user_id IS NULL AND ?2 IS NULL AND username = ?3) AND quote = ?4;",
+        guild_id,
+        entry.user_id,
+        entry.username,
+        quote
+    )
+    .execute(&mut transaction)
+    .await?;
+    let existing = query!(
+        "SELECT id FROM quotes WHERE id != ?1 AND guild_id = ?2
+        AND (user_id = ?3 OR user_id IS NULL AND ?3 IS NULL AND username = ?4) AND quote = ?5;",
+        id,
+        guild_id,
+        entry.user_id,
+        entry.username,
+        quote
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    if let Some(existing) = existing {
+        let response = format!("That quote already exists as #{}", existing.id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let updated = query!(
+        "UPDATE quotes SET quote = ? WHERE id = ? AND guild_id = ? AND deleted_at IS NULL;",
+        quote,
+        id,
+        guild_id
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    if updated == 0 {
+        let response = format!("No quote with id {} found", id);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let code_blocks = ctx.data().quote_style(&guild_id).code_blocks;
+    let mut response = serenity::MessageBuilder::new();
+    response.push(format!("Quote #{} changed from ", id));
+    push_quote_text(&mut response, &entry.quote, code_blocks).push(" to ");
+    push_quote_text(&mut response, &quote, code_blocks);
+    let response = response.build();
+    ctx.send(|f| f.content(response).allowed_mentions(|m| m.empty_parse()))
+        .await?;
+    Ok(())
+}
+
+/// Search all quotes for words, best matches first
+///
+/// Quotes match when they contain every word, or words starting with them. Case and accents are
+/// ignored.
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn search(
+    ctx: Context<'_>,
+    #[description = "Words to look for"] text: String,
+    #[description = "Only show quotes with this tag"] tag: Option<String>,
+    #[description = "Only show this page, without buttons"]
+    #[min = 1]
+    page: Option<u32>,
+) -> Result<(), Error> {
+    let terms = if let Some(terms) = fts_query(&text) {
+        terms
+    } else {
+        ctx.send(|f| f.content("Search for at least one word").ephemeral(true))
+            .await?;
+        return Ok(());
+    };
+    let guild_id = guild_id(ctx)?;
+    let tag = tag.map(|tag| tag.trim().to_lowercase());
+    let per_page = ctx.data().results_per_page(&guild_id);
+    let (limit, offset) = page_bounds(page, per_page);
+    let entries = query!(
+        r#"SELECT quotes.id as "id!", user_id, username, quotes.quote as "quote!",
+        quote_date as "quote_date!", created_at, COUNT(*) OVER () as "total!: i64"
+        FROM quotes_fts JOIN quotes ON quotes.id = quotes_fts.rowid
+        WHERE quotes_fts MATCH ?1
+        AND (guild_id = ?2 OR guild_id IS NULL)
+        AND (?3 IS NULL OR quotes.id IN (SELECT quote_id FROM tags WHERE tag = ?3))
+        AND deleted_at IS NULL
+        ORDER BY quotes_fts.rank LIMIT ?4 OFFSET ?5;"#,
+        terms,
+        guild_id,
+        tag,
+        limit,
+        offset
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    if entries.is_empty() {
+        let response = match page {
+            Some(page) if page > 1 => format!("There's no page {}", page),
+            _ => format!("No quotes matching: {} ", text),
+        };
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let style = ctx.data().quote_style(&guild_id);
+    let mut lines = Vec::new();
+    for entry in &entries {
+        let mut line = serenity::MessageBuilder::new();
+        push_quote_line(
+            &mut line,
+            entry.id,
+            entry.user_id.as_deref(),
+            entry.username.as_deref(),
+            &entry.quote,
+            &quote_day(&entry.quote_date, entry.created_at, &style),
+            style.code_blocks,
+        )?;
+        lines.push(line.build());
+    }
+    let pages = split_pages(&lines, per_page);
+    send_pages(ctx, &pages, page, entries[0].total, per_page).await
+}
+
+/// Find out who said something, with the ids of their quotes that match
+///
+/// Matches the same way as /quote search, most matching quotes first.
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn whosaid(
+    ctx: Context<'_>,
+    #[description = "Words to look for"]
+    #[rest]
+    text: String,
+) -> Result<(), Error> {
+    let terms = if let Some(terms) = fts_query(&text) {
+        terms
+    } else {
+        ctx.send(|f| f.content("Search for at least one word").ephemeral(true))
+            .await?;
+        return Ok(());
+    };
+    let guild_id = guild_id(ctx)?;
+    let authors = query!(
+        r#"SELECT user_id, MAX(username) as "username: String", COUNT(*) as "count!: i64",
+        json_group_array(quotes.id) as "ids!: String"
+        FROM quotes_fts JOIN quotes ON quotes.id = quotes_fts.rowid
+        WHERE quotes_fts MATCH ?1
+        AND (guild_id = ?2 OR guild_id IS NULL)
+        AND deleted_at IS NULL
+        GROUP BY user_id, CASE WHEN user_id IS NULL THEN username END
+        ORDER BY COUNT(*) DESC, MIN(quotes_fts.rank);"#,
+        terms,
+        guild_id
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    if authors.is_empty() {
+        let response = format!("Nobody said: {} ", text);
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let mut lines = Vec::new();
+    for author in &authors {
+        let user = parse_author(author.user_id.as_deref())?;
+        let mut ids: Vec<i64> =
+            serde_json::from_str(&author.ids).map_err(|_| DatabaseError::MalformedEntry)?;
+        ids.sort_unstable();
+        let ids: Vec<String> = ids.iter().map(|id| format!("#{}", id)).collect();
+        let mut line = serenity::MessageBuilder::new();
+        match (user, &author.username) {
+            (Some(user), _) => line.mention(&user),
+            (None, Some(username)) => line.push_safe(escape_markdown(username)),
+            (None, None) => return Err(DatabaseError::MalformedEntry.into()),
+        };
+        line.push(format!(
+            " - {} quote(s): {}\n",
+            author.count,
+            ids.join(", ")
+        ));
+        lines.push(line.build());
+    }
+    let pages = split_pages(&lines, ctx.data().results_per_page(&guild_id));
+    paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// List the quotes added on a particular day
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn on(
+    ctx: Context<'_>,
+    #[description = "Day the quotes were added, like 2023-12-25"] date: String,
+    #[description = "Only show quotes by this user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let day = match parse_day(&date) {
+        Ok(day) => day,
+        Err(problem) => {
+            ctx.send(|f| f.content(problem).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let guild_id = guild_id(ctx)?;
+    let user_id = user.as_ref().map(|user| user.id.as_u64().to_string());
+    let entries = query!(
+        "SELECT * FROM quotes WHERE quote_date = ?1 AND (?2 IS NULL OR user_id = ?2)
+        AND (guild_id = ?3 OR guild_id IS NULL) AND deleted_at IS NULL ORDER BY id;",
+        day,
+        user_id,
+        guild_id
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    if entries.is_empty() {
+        let response = if let Some(user) = user {
+            let message = ctx.data().no_quotes_message(&guild_id, &[&user.name]);
+            format!("{} on {}", message, day)
+        } else {
+            format!("No quotes found on {}", day)
+        };
+        ctx.send(|f| {
+            f.content(response)
+                .ephemeral(true)
+                .allowed_mentions(|m| m.empty_parse())
+        })
+        .await?;
+        return Ok(());
+    }
+    let style = ctx.data().quote_style(&guild_id);
+    let mut lines = Vec::new();
+    for entry in &entries {
+        let mut line = serenity::MessageBuilder::new();
+        push_quote_line(
+            &mut line,
+            entry.id,
+            entry.user_id.as_deref(),
+            entry.username.as_deref(),
+            &entry.quote,
+            &quote_day(&entry.quote_date, entry.created_at, &style),
+            style.code_blocks,
+        )?;
+        lines.push(line.build());
+    }
+    let pages = split_pages(&lines, ctx.data().results_per_page(&guild_id));
+    paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// List the quotes with the best score from votes, best first
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn top(
+    ctx: Context<'_>,
+    #[description = "Only show this page, without buttons"]
+    #[min = 1]
+    page: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let per_page = ctx.data().results_per_page(&guild_id);
+    let (limit, offset) = page_bounds(page, per_page);
+    let entries = query!(
+        r#"SELECT quotes.id as "id!", user_id, username, quote as "quote!",
+        quote_date as "quote_date!", created_at, SUM(vote) as "score!: i64",
+        COUNT(*) OVER () as "total!: i64"
+        FROM quotes JOIN votes ON votes.quote_id = quotes.id
+        WHERE (guild_id = ?1 OR guild_id IS NULL) AND deleted_at IS NULL
+        GROUP BY quotes.id ORDER BY SUM(vote) DESC, quote_date DESC, quotes.id DESC
+        LIMIT ?2 OFFSET ?3;"#,
+        guild_id,
+        limit,
+        offset
+    )
+    .fetch_all(&ctx.data().database)
+    .await?;
+    if entries.is_empty() {
+        let response = match page {
+            Some(page) if page > 1 => format!("There's no page {}", page),
+            _ => {
+                "No quotes have been voted on yet, vote with the buttons under a quote".to_string()
+            }
+        };
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let style = ctx.data().quote_style(&guild_id);
+    let mut lines = Vec::new();
+    for entry in &entries {
+        let mut line = serenity::MessageBuilder::new();
+        line.push(format!("{:+} ", entry.score));
+        push_quote_line(
+            &mut line,
+            entry.id,
+            entry.user_id.as_deref(),
+            entry.username.as_deref(),
+            &entry.quote,
+            &quote_day(&entry.quote_date, entry.created_at, &style),
+            style.code_blocks,
+        )?;
+        lines.push(line.build());
+    }
+    let pages = split_pages(&lines, per_page);
+    send_pages(ctx, &pages, page, entries[0].total, per_page).await
+}
+
+/// Count the quotes of a particular user, or of the whole server if no user is given
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn count(
+    ctx: Context<'_>,
+    #[description = "Selected user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let user_id = user.as_ref().map(|user| user.id.as_u64().to_string());
+    let count = query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM quotes WHERE (?1 IS NULL OR user_id = ?1)
+        AND (guild_id = ?2 OR guild_id IS NULL) AND deleted_at IS NULL;"#,
+        user_id,
+        guild_id
+    )
+    .fetch_one(&ctx.data().database)
+    .await?
+    .count;
+    let response = match (user, count) {
+        (Some(user), 0) => format!("{} hasn't been quoted yet", user.name),
+        (Some(user), count) => format!("{} has {} quote(s)", user.name, count),
+        (None, count) => format!("This server has {} quote(s)", count),
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// List what /quote can do
+#[poise::command(slash_command, prefix_command)]
+async fn help(ctx: Context<'_>) -> Result<(), Error> {
+    let quote = ctx
+        .framework()
+        .options()
+        .commands
+        .iter()
+        .find(|command| command.name == "quote");
+    let pages = if let Some(quote) = quote {
+        help_pages(quote)
+    } else {
+        return Ok(());
+    };
+    paginate(ctx, &pages).await?;
+    Ok(())
+}
+
+/// The usage and description of every subcommand of `parent`, one per line, split into pages that
+/// fit in a message.
+fn help_pages(parent: &poise::Command<Data, Error>) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    for command in &parent.subcommands {
+        let mut line = format!("`/{} {}", parent.name, command.name);
+        for parameter in &command.parameters {
+            if parameter.required {
+                line.push_str(&format!(" <{}>", parameter.name));
+            } else {
+                line.push_str(&format!(" [{}]", parameter.name));
+            }
+        }
+        line.push('`');
+        if let Some(description) = &command.description {
+            line.push_str(&format!(" - {}", description));
+        }
+        line.push('\n');
+        if page.chars().count() + line.chars().count() > MESSAGE_LENGTH_LIMIT - PAGE_HEADER_LENGTH {
+            pages.push(std::mem::take(&mut page));
+        }
+        page.push_str(&line);
+    }
+    pages.push(page);
+    pages
+}
+
+/// Show which commands are used most in this server, as logged with /config usage-log
+#[poise::command(slash_command, prefix_command, guild_only, check = "is_moderator")]
+async fn usage(
+    ctx: Context<'_>,
+    #[description = "How many days back to look, 30 if not given"]
+    #[min = 1]
+    #[max = 90]
+    days: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let days = days.unwrap_or(DEFAULT_USAGE_DAYS).clamp(1, USAGE_LOG_DAYS);
+    let since = Utc::now() - chrono::Duration::days(days.into());
+    let entries = command_usage(&ctx.data().database, &guild_id, since).await?;
+    if entries.is_empty() {
+        let response = if ctx.data().settings(&guild_id).usage_log.unwrap_or(false) {
+            format!("No commands were used in the last {} day(s)", days)
+        } else {
+            "Commands aren't logged in this server, turn that on with /config usage-log".to_string()
+        };
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let mut response = format!("Most used commands in the last {} day(s)\n", days);
+    for entry in entries {
+        response.push_str(&format!(
+            "`/{}` - {} time(s) by {} member(s)\n",
+            entry.command, entry.count, entry.users
+        ));
+    }
+    ctx.send(|f| f.content(response).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// How often one command was used, as counted by `command_usage`.
+struct CommandUsage {
+    command: String,
+    count: i64,
+    /// How many different members used it.
+    users: i64,
+}
+
+/// The `USAGE_COMMANDS` commands logged most often in the guild with id `guild_id` since `since`,
+/// most used first.
+async fn command_usage(
+    database: &Pool<Sqlite>,
+    guild_id: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<CommandUsage>, Error> {
+    let entries = query_as!(
+        CommandUsage,
+        r#"SELECT command as "command!", COUNT(*) as "count!: i64",
+        COUNT(DISTINCT user_id) as "users!: i64"
+        FROM command_log WHERE guild_id = ? AND invoked_at >= ?
+        GROUP BY command ORDER BY COUNT(*) DESC, command LIMIT ?;"#,
+        guild_id,
+        since,
+        USAGE_COMMANDS
+    )
+    .fetch_all(database)
+    .await?;
+    Ok(entries)
+}
+
+/// Log the invoked command if its guild turned on /config usage-log. Failing to is only logged, as
+/// the command should run either way.
+async fn log_command(ctx: Context<'_>) {
+    let guild_id = if let Some(guild_id) = ctx.guild_id() {
+        guild_id.as_u64().to_string()
+    } else {
+        return;
+    };
+    let command = &ctx.command().qualified_name;
+    let user_id = ctx.author().id.as_u64().to_string();
+    let logged = record_command(ctx.data(), &guild_id, command, &user_id, Utc::now()).await;
+    if let Err(e) = logged {
+        error!(error = %e, "Couldn't log a command");
+    }
+}
+
+/// Log that the user with id `user_id` used `command` in the guild with id `guild_id` at `now`,
+/// unless the guild didn't turn on /config usage-log. Returns whether it was logged.
+async fn record_command(
+    data: &Data,
+    guild_id: &str,
+    command: &str,
+    user_id: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, Error> {
+    if !data.settings(guild_id).usage_log.unwrap_or(false) {
+        return Ok(false);
+    }
+    query!(
+        "INSERT INTO command_log (command, user_id, guild_id, invoked_at) VALUES (?,?,?,?);",
+        command,
+        user_id,
+        guild_id,
+        now
+    )
+    .execute(&data.database)
+    .await?;
+    Ok(true)
+}
+
+/// Show a summary of this server's quotes, or of a particular user's
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn stats(
+    ctx: Context<'_>,
+    #[description = "Selected user"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let user_id = user.as_ref().map(|user| user.id.as_u64().to_string());
+    let totals = query!(
+        r#"SELECT COUNT(*) as "count!: i64", COUNT(DISTINCT user_id) as "users!: i64",
+        MIN(quote_date) as "oldest: String", MAX(quote_date) as "newest: String",
+        AVG(LENGTH(quote)) as "average_length: f64" FROM quotes
+        WHERE (?1 IS NULL OR user_id = ?1) AND (guild_id = ?2 OR guild_id IS NULL)
+        AND deleted_at IS NULL;"#,
+        user_id,
+        guild_id
+    )
+    .fetch_one(&ctx.data().database)
+    .await?;
+    let (oldest, newest, average_length) =
+        if let (Some(oldest), Some(newest), Some(average_length)) =
+            (totals.oldest, totals.newest, totals.average_length)
+        {
+            (oldest, newest, average_length)
+        } else {
+            let response = if let Some(user) = user {
+                format!("{} hasn't been quoted yet", user.name)
+            } else {
+                "No quotes found".to_string()
+            };
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        };
+    let top_contributor = query!(
+        r#"SELECT added_by as "added_by!", COUNT(*) as "count!: i64" FROM quotes
+        WHERE (?1 IS NULL OR user_id = ?1) AND (guild_id = ?2 OR guild_id IS NULL)
+        AND deleted_at IS NULL AND added_by IS NOT NULL
+        GROUP BY added_by ORDER BY COUNT(*) DESC LIMIT 1;"#,
+        user_id,
+        guild_id
+    )
+    .fetch_optional(&ctx.data().database)
+    .await?;
+    let top_contributor = if let Some(top) = top_contributor {
+        let added_by = top
+            .added_by
+            .parse()
+            .map_err(|_| DatabaseError::MalformedEntry)?;
+        serenity::MessageBuilder::new()
+            .mention(&serenity::UserId(added_by))
+            .push(format!(" - {} quote(s)", top.count))
+            .build()
+    } else {
+        "Unknown".to_string()
+    };
+    let title = if let Some(user) = &user {
+        format!("Quotes of {}", user.name)
+    } else {
+        "Quotes of this server".to_string()
+    };
+    ctx.send(|f| {
+        f.embed(|e| {
+            e.title(title)
+                .field("Quotes", totals.count, true)
+                .field("Quoted users", totals.users, true)
+                .field("Top contributor", top_contributor, true)
+                .field("Oldest", oldest, true)
+                .field("Newest", newest, true)
+                .field(
+                    "Average length",
+                    format!("{:.0} characters", average_length),
+                    true,
+                )
+        })
+    })
+    .await?;
+    Ok(())
+}
+
+/// List every tag used in this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn tags(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let tags: Vec<String> = query!(
+        "SELECT DISTINCT tag FROM tags JOIN quotes ON quotes.id = tags.quote_id
+        WHERE (guild_id = ? OR guild_id IS NULL) AND deleted_at IS NULL ORDER BY tag;",
+        guild_id
+    )
+    .fetch_all(&ctx.data().database)
+    .await?
+    .into_iter()
+    .map(|entry| entry.tag)
+    .collect();
+    if tags.is_empty() {
+        ctx.send(|f| f.content("No quotes have been tagged yet").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+    let response = serenity::MessageBuilder::new()
+        .push("Tags: ")
+        .push_safe(tags.join(", "))
+        .build();
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Stop others from saving your messages as quotes, in every server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn optout(
+    ctx: Context<'_>,
+    #[description = "Also delete the quotes of yours that are already stored"]
+    delete_quotes: Option<bool>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id.as_u64().to_string();
+    query!(
+        "INSERT OR IGNORE INTO optout (user_id) VALUES (?);",
+        user_id
+    )
+    .execute(&ctx.data().database)
+    .await?;
+    if !delete_quotes.unwrap_or(false) {
+        ctx.send(|f| {
+            f.content("You won't be quoted anymore, use /quote optin to allow it again")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let stored = query!(
+        r#"SELECT COUNT(*) as "c!: i64" FROM quotes WHERE user_id = ? AND deleted_at IS NULL;"#,
+        user_id
+    )
+    .fetch_one(&ctx.data().database)
+    .await?
+    .c;
+    let prompt = format!(
+        "You won't be quoted anymore. Also delete all {} quote(s) of yours, in every server?",
+        stored
+    );
+    let (confirmed, reply) = confirm(ctx, prompt).await?;
+    let response = if confirmed {
+        let deleted = query!("DELETE FROM quotes WHERE user_id = ?;", user_id)
+            .execute(&ctx.data().database)
+            .await?
+            .rows_affected();
+        ctx.data().forget_quote_ids();
+        format!("Deleted {} quote(s)", deleted)
+    } else {
+        "Your existing quotes were kept".to_string()
+    };
+    reply
+        .edit(ctx, |f| f.content(response).components(|c| c))
+        .await?;
+    Ok(())
+}
+
+/// Allow your messages to be saved as quotes again
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn optin(ctx: Context<'_>) -> Result<(), Error> {
+    let user_id = ctx.author().id.as_u64().to_string();
+    query!("DELETE FROM optout WHERE user_id = ?;", user_id)
+        .execute(&ctx.data().database)
+        .await?;
+    ctx.send(|f| f.content("You can be quoted again").ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Change how the bot behaves in this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "show",
+        "quote_of_the_day",
+        "max_quote_length",
+        "add_cooldown",
+        "reaction_quotes",
+        "moderator_role",
+        "timezone",
+        "date_format",
+        "moderation",
+        "code_blocks",
+        "anniversaries",
+        "usage_log",
+        "no_quotes_message",
+        "results_per_page"
+    ),
+    default_member_permissions = "MANAGE_GUILD",
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn config(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show the current settings of this server
+#[poise::command(slash_command, guild_only)]
+async fn show(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let settings = ctx.data().settings(&guild_id);
+    let mut response = serenity::MessageBuilder::new();
+    response.push("quote-of-the-day: ");
+    if let Some(channel) = settings.quote_of_the_day_channel {
+        let channel = channel.parse().map_err(|_| DatabaseError::MalformedEntry)?;
+        response.channel(serenity::ChannelId(channel));
+    } else {
+        response.push("off");
+    }
+    response.push("\nmax-quote-length: ");
+    if let Some(length) = settings.max_quote_length {
+        response.push(length);
+    } else {
+        response.push(format!("{} (default)", ctx.data().default_max_quote_length));
+    }
+    response.push("\nadd-cooldown: ");
+    if let Some(seconds) = settings.add_cooldown {
+        response.push(format!("{}s", seconds));
+    } else {
+        response.push(format!("{}s (default)", DEFAULT_ADD_COOLDOWN.as_secs()));
+    }
+    response.push("\nreaction-quotes: ");
+    if let (Some(emoji), Some(threshold)) = (settings.reaction_emoji, settings.reaction_threshold) {
+        response.push(format!("{} x{}", emoji, threshold));
+    } else {
+        response.push("off");
+    }
+    if !ctx.data().reaction_quotes {
+        response.push(" (not running with --reaction-quotes)");
+    }
+    response.push("\nmoderator-role: ");
+    if let Some(role) = settings.moderator_role {
+        let role = role.parse().map_err(|_| DatabaseError::MalformedEntry)?;
+        response.role(serenity::RoleId(role));
+    } else {
+        response.push("none");
+    }
+    response.push(format!(
+        "\nmoderation: {}",
+        if settings.moderation.unwrap_or(false) {
+            "on"
+        } else {
+            "off"
+        }
+    ));
+    let style = ctx.data().quote_style(&guild_id);
+    response.push(format!("\ntimezone: {}", style.timezone));
+    response
+        .push("\ndate-format: ")
+        .push_mono_safe(&style.format);
+    response.push(format!(
+        "\ncode-blocks: {}",
+        if style.code_blocks { "on" } else { "off" }
+    ));
+    response.push(format!(
+        "\nanniversaries: {}",
+        if settings.anniversaries.unwrap_or(false) {
+            "on"
+        } else {
+            "off"
+        }
+    ));
+    response.push(format!(
+        "\nusage-log: {}",
+        if settings.usage_log.unwrap_or(false) {
+            "on"
+        } else {
+            "off"
+        }
+    ));
+    response.push("\nresults-per-page: ");
+    if let Some(count) = settings.results_per_page {
+        response.push(count);
+    } else {
+        response.push(format!("{} (default)", QUOTES_PER_PAGE));
+    }
+    response.push("\nno-quotes-message: ");
+    if let Some(template) = &settings.no_quotes_message {
+        response.push_mono_safe(template);
+    } else {
+        response
+            .push_mono_safe(DEFAULT_NO_QUOTES_MESSAGE)
+            .push(" (default)");
+    }
+    let response = response.build();
+    ctx.send(|f| f.content(response).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Change how many quotes are listed per page and shown by /quote random at once
+#[poise::command(slash_command, guild_only, rename = "results-per-page")]
+async fn results_per_page(
+    ctx: Context<'_>,
+    #[description = "Most quotes per page, leave out for 10"]
+    #[min = 1]
+    #[max = 25]
+    count: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let count = count.map(|count| count.clamp(1, MAX_RESULTS_PER_PAGE));
+    query!(
+        "INSERT INTO guild_settings (guild_id, results_per_page) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET results_per_page = excluded.results_per_page;",
+        guild_id,
+        count
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the results per page");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    ctx.say(format!(
+        "Lists now show up to {} quote(s) per page",
+        ctx.data().results_per_page(&guild_id)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Change the reply for when no quotes of someone match, {user} is replaced with their name
+#[poise::command(slash_command, guild_only, rename = "no-quotes-message")]
+async fn no_quotes_message(
+    ctx: Context<'_>,
+    #[description = "Reply like \"Nothing from {user} yet\", leave out for the default"]
+    message: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    if let Some(message) = &message {
+        if !valid_no_quotes_message(message) {
+            let response = format!(
+                "The message can be 1 to {} characters long, with no braces other than in {{user}}",
+                MAX_NO_QUOTES_MESSAGE_LENGTH
+            );
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        }
+    }
+    query!(
+        "INSERT INTO guild_settings (guild_id, no_quotes_message) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET no_quotes_message = excluded.no_quotes_message;",
+        guild_id,
+        message
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the no quotes message");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let example = ctx
+        .data()
+        .no_quotes_message(&guild_id, &[&ctx.author().name]);
+    ctx.send(|f| {
+        f.content(format!(
+            "When no quotes match, the reply is now: {}",
+            example
+        ))
+        .allowed_mentions(|m| m.empty_parse())
+    })
+    .await?;
+    Ok(())
+}
+
+/// Post a random quote to a channel every day, or stop if no channel is given
+#[poise::command(slash_command, guild_only, rename = "quote-of-the-day")]
+async fn quote_of_the_day(
+    ctx: Context<'_>,
+    #[description = "Channel to post in"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let channel_id = channel
+        .as_ref()
+        .map(|channel| channel.id.as_u64().to_string());
+    query!(
+        "INSERT INTO guild_settings (guild_id, quote_of_the_day_channel) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET quote_of_the_day_channel = excluded.quote_of_the_day_channel;",
+        guild_id,
+        channel_id
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the quote of the day channel");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    if let Some(channel) = channel {
+        let response = serenity::MessageBuilder::new()
+            .push("The quote of the day will be posted in ")
+            .channel(channel.id)
+            .build();
+        ctx.say(response).await?;
+    } else {
+        ctx.say("The quote of the day won't be posted anymore")
+            .await?;
+    }
+    Ok(())
+}
+
+/// Limit how long new quotes can be, or go back to the default if no length is given
+#[poise::command(slash_command, guild_only, rename = "max-quote-length")]
+async fn max_quote_length(
+    ctx: Context<'_>,
+    #[description = "Most characters a quote can have"]
+    #[min = 1]
+    length: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    query!(
+        "INSERT INTO guild_settings (guild_id, max_quote_length) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET max_quote_length = excluded.max_quote_length;",
+        guild_id,
+        length
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the maximum quote length");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    ctx.say(format!(
+        "Quotes can now be up to {} characters long",
+        ctx.data().max_quote_length(&guild_id)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Change how long members wait between adding quotes, or go back to the default if none is given
+#[poise::command(slash_command, guild_only, rename = "add-cooldown")]
+async fn add_cooldown(
+    ctx: Context<'_>,
+    #[description = "Seconds between added quotes, 0 for no wait"]
+    #[max = 3600]
+    seconds: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    query!(
+        "INSERT INTO guild_settings (guild_id, add_cooldown) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET add_cooldown = excluded.add_cooldown;",
+        guild_id,
+        seconds
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the add cooldown");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let seconds = seconds.map_or(DEFAULT_ADD_COOLDOWN.as_secs(), u64::from);
+    let response = if seconds == 0 {
+        "Quotes can now be added without waiting".to_string()
+    } else {
+        format!(
+            "Members now wait {} second(s) between added quotes",
+            seconds
+        )
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Save messages as quotes once they get enough of a reaction, or stop if no threshold is given
+#[poise::command(slash_command, guild_only, rename = "reaction-quotes")]
+async fn reaction_quotes(
+    ctx: Context<'_>,
+    #[description = "How many reactions a message needs"]
+    #[min = 1]
+    threshold: Option<u32>,
+    #[description = "Reaction to count, by default a star"] emoji: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    // Reactions aren't even looked at then, turning them off still works
+    if threshold.is_some() && !ctx.data().reaction_quotes {
+        ctx.send(|f| {
+            f.content(
+                "Reaction quotes are off for this bot, whoever runs it has to start it with \
+                --reaction-quotes first",
+            )
+            .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+    let emoji = if threshold.is_some() {
+        let emoji = emoji.as_deref().unwrap_or("⭐").trim();
+        if let Ok(emoji) = serenity::ReactionType::try_from(emoji) {
+            Some(emoji.to_string())
+        } else {
+            let response = format!("{} isn't an emoji", emoji);
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        }
+    } else {
+        None
+    };
+    query!(
+        "INSERT INTO guild_settings (guild_id, reaction_emoji, reaction_threshold) VALUES (?,?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET reaction_emoji = excluded.reaction_emoji,
+        reaction_threshold = excluded.reaction_threshold;",
+        guild_id,
+        emoji,
+        threshold
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the reaction quote settings");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    if let (Some(emoji), Some(threshold)) = (emoji, threshold) {
+        let response = format!(
+            "Messages with {} {} reaction(s) will be saved as quotes",
+            threshold, emoji
+        );
+        ctx.say(response).await?;
+    } else {
+        ctx.say("Messages won't be saved by reactions anymore")
+            .await?;
+    }
+    Ok(())
+}
+
+/// Let members of a role delete and edit quotes, or only those with Manage Messages if no role is
+/// given
+#[poise::command(slash_command, guild_only, rename = "moderator-role")]
+async fn moderator_role(
+    ctx: Context<'_>,
+    #[description = "Role of quote moderators"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let role_id = role.as_ref().map(|role| role.id.as_u64().to_string());
+    query!(
+        "INSERT INTO guild_settings (guild_id, moderator_role) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET moderator_role = excluded.moderator_role;",
+        guild_id,
+        role_id
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the moderator role");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let response = if let Some(role) = role {
+        serenity::MessageBuilder::new()
+            .push("Members of ")
+            .role(role.id)
+            .push(" can now delete and edit quotes")
+            .build()
+    } else {
+        "Only members with the Manage Messages permission can delete and edit quotes now"
+            .to_string()
+    };
+    // Don't ping everyone with the role
+    ctx.send(|f| f.content(response).allowed_mentions(|m| m.empty_parse()))
+        .await?;
+    Ok(())
+}
+
+/// Make quotes added by members other than moderators wait for a moderator's approval
+#[poise::command(slash_command, guild_only)]
+async fn moderation(
+    ctx: Context<'_>,
+    #[description = "Whether added quotes need approval"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    query!(
+        "INSERT INTO guild_settings (guild_id, moderation) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET moderation = excluded.moderation;",
+        guild_id,
+        enabled
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the moderation setting");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let response = if enabled {
+        "Added quotes now wait for a moderator's approval, see /quote pending"
+    } else {
+        "Added quotes are saved right away now"
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Show dates in a timezone other than UTC
+#[poise::command(slash_command, guild_only)]
+async fn timezone(
+    ctx: Context<'_>,
+    #[description = "Name of the timezone, like Europe/Warsaw, leave out for UTC"] name: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let name = name.map(|name| name.trim().to_string());
+    if let Some(name) = &name {
+        if name.parse::<Tz>().is_err() {
+            let response = format!(
+                "Unknown timezone {}, use a name like Europe/Warsaw or America/New_York",
+                name
+            );
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        }
+    }
+    query!(
+        "INSERT INTO guild_settings (guild_id, timezone) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET timezone = excluded.timezone;",
+        guild_id,
+        name
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the timezone");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    ctx.say(format!(
+        "Dates are now shown in {}",
+        ctx.data().quote_style(&guild_id).timezone
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Change how dates are shown
+#[poise::command(slash_command, guild_only, rename = "date-format")]
+async fn date_format(
+    ctx: Context<'_>,
+    #[description = "strftime pattern, like %d.%m.%Y, leave out for %Y-%m-%d"] format: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    if let Some(format) = &format {
+        if !valid_date_format(format) {
+            let response = serenity::MessageBuilder::new()
+                .push("Invalid date format ")
+                .push_mono_safe(format)
+                .push(
+                    ", see https://docs.rs/chrono/latest/chrono/format/strftime/ for the patterns",
+                )
+                .build();
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        }
+    }
+    query!(
+        "INSERT INTO guild_settings (guild_id, date_format) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET date_format = excluded.date_format;",
+        guild_id,
+        format
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the date format");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let style = ctx.data().quote_style(&guild_id);
+    let example = Utc::now()
+        .with_timezone(&style.timezone)
+        .format(&style.format)
+        .to_string();
+    ctx.say(format!("Dates are now shown like {}", example))
+        .await?;
+    Ok(())
+}
+
+/// Show the users with the most quotes
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn leaderboard(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    let cached = ctx
+        .data()
+        .leaderboards
+        .lock()
+        .unwrap()
+        .get(&guild_id)
+        .filter(|(computed, _)| computed.elapsed() < LEADERBOARD_CACHE_TTL)
+        .map(|(_, counts)| counts.clone());
+    let counts = if let Some(counts) = cached {
+        counts
+    } else {
+        let counts: Leaderboard = query!(
+            r#"SELECT user_id, MAX(username) as "username: String", COUNT(*) as "c!: i64" FROM quotes
+            WHERE (guild_id = ? OR guild_id IS NULL) AND deleted_at IS NULL
+            GROUP BY user_id, CASE WHEN user_id IS NULL THEN username END
+            ORDER BY COUNT(*) DESC LIMIT ?;"#,
+            guild_id,
+            LEADERBOARD_SIZE
+        )
+        .fetch_all(&ctx.data().database)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.user_id, entry.username, entry.c))
+        .collect();
+        ctx.data()
+            .leaderboards
+            .lock()
+            .unwrap()
+            .insert(guild_id, (Instant::now(), counts.clone()));
+        counts
+    };
+    if counts.is_empty() {
+        ctx.send(|f| f.content("No quotes found").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let mut board = serenity::MessageBuilder::new();
+    for (place, (user_id, username, count)) in counts.iter().enumerate() {
+        board.push(format!("{}. ", place + 1));
+        match (user_id, parse_author(user_id.as_deref())?, username) {
+            (Some(user_id), Some(user), username) => {
+                // Members that left the guild can't be mentioned meaningfully, so show their name,
+                // or their id without one, instead. Discord is only asked for members the cache
+                // doesn't have.
+                let is_member = match ctx.guild_id() {
+                    Some(guild) if ctx.serenity_context().cache.member(guild, user).is_none() => {
+                        guild.member(ctx, user).await.is_ok()
+                    }
+                    _ => true,
+                };
+                match (is_member, username) {
+                    (true, _) => board.mention(&user),
+                    (false, Some(username)) => board.push_safe(escape_markdown(username)),
+                    (false, None) => board.push(user_id),
+                };
+            }
+            (_, _, Some(username)) => {
+                board.push_safe(escape_markdown(username));
+            }
+            _ => return Err(DatabaseError::MalformedEntry.into()),
+        }
+        board.push(format!(" - {} quote(s)\n", count));
+    }
+    let board = board.build();
+    ctx.send(|f| f.embed(|e| e.title("Most quoted users").description(board)))
+        .await?;
+    Ok(())
+}
+
+/// Check that the bot is responsive
+#[poise::command(slash_command)]
+async fn ping(ctx: Context<'_>) -> Result<(), Error> {
+    let latency = ctx.ping().await;
+    // Discord hasn't acknowledged a heartbeat yet right after connecting
+    let response = if latency.is_zero() {
+        "Pong! Gateway latency hasn't been measured yet".to_string()
+    } else {
+        format!("Pong! Gateway latency: {} ms", latency.as_millis())
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Show how long the bot has been running
+#[poise::command(slash_command)]
+async fn uptime(ctx: Context<'_>) -> Result<(), Error> {
+    let response = format!("Up for {}", format_uptime(ctx.data().started.elapsed()));
+    ctx.say(response).await?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = Cli::command().get_matches();
+    let mut cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(e) => e.exit(),
+    };
+    if let Some(path) = cli.config.clone() {
+        if let Err(e) = config::apply(&mut cli, &matches, &path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    let filter = match EnvFilter::try_new(&cli.log_level) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Invalid log level {}: {}", cli.log_level, e);
+            std::process::exit(1);
+        }
+    };
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+    let path = cli
+        .database
+        .clone()
+        .unwrap_or_else(|| "database.sqlite".to_string());
+    let busy_timeout = Duration::from_secs(cli.busy_timeout);
+    if let Some(command) = cli.command {
+        let result = match command {
+            Command::Migrate => {
+                maintenance::migrate(&path, cli.max_connections, busy_timeout).await
+            }
+            Command::Export { output } => {
+                maintenance::export(&path, cli.max_connections, busy_timeout, output.as_deref())
+                    .await
+            }
+            Command::Import {
+                input,
+                skip_duplicates,
+            } => {
+                maintenance::import(
+                    &path,
+                    cli.max_connections,
+                    busy_timeout,
+                    &input,
+                    skip_duplicates,
+                )
+                .await
+            }
+            Command::Vacuum => maintenance::vacuum(&path, cli.max_connections, busy_timeout).await,
+            Command::Backup { output } => {
+                maintenance::backup(&path, cli.max_connections, busy_timeout, &output).await
+            }
+        };
+        if let Err(e) = result {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if cli.unit {
+        let group = cli.unit_group.as_deref().unwrap_or(&cli.unit_user);
+        println!(
+            "{}",
+            systemd_unit(&cli.database, &cli.guild, &cli.unit_user, group)
+        );
+        return;
+    }
+    let token = if let Some(path) = &cli.token_file {
+        match std::fs::read_to_string(path) {
+            Ok(token) => token.trim_end().to_string(),
+            Err(e) => {
+                error!("Couldn't read token file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(token) = cli.token {
+        token
+    } else if let Ok(token) = std::env::var("DISCORD_TOKEN") {
+        token
+    } else {
+        error!("No token provided via --token, --token-file or DISCORD_TOKEN");
+        // Someone trying the bot out by hand gets pointed at the options, a service only logs
+        if std::io::stderr().is_terminal() {
+            eprintln!("{}", NO_TOKEN_HINT);
+        }
+        std::process::exit(1);
+    };
+    if cli.unregister {
+        if let Err(e) = unregister(&token, &cli.guild).await {
+            error!("Couldn't unregister commands: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if cli.register_only {
+        if let Err(e) = register_only(&token, &cli.guild, cli.register_globally).await {
+            error!("Couldn't register commands: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let data = match Data::from(
+        &path,
+        cli.max_connections,
+        busy_timeout,
+        cli.max_quote_length,
+        cli.recent_quotes,
+        cli.keep_deleted_days,
+        cli.reaction_quotes,
+    )
+    .await
+    {
+        Ok(data) => data,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let database = data.database.clone();
+    if let Some(addr) = cli.metrics_addr {
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Couldn't serve metrics on {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        };
+        info!("Serving metrics on http://{}/metrics", addr);
+        tokio::spawn(metrics::serve(listener, data.metrics.clone()));
+        tokio::spawn(metrics::refresh_quote_count(
+            database.clone(),
+            data.metrics.clone(),
+        ));
+    }
+    let quote_of_the_day_time = cli.quote_of_the_day_time;
+    let guilds = cli.guild.clone();
+    let register_globally = cli.register_globally;
+    let mut intents = serenity::GatewayIntents::non_privileged();
+    if cli.reaction_quotes {
+        // Without it the content of the reacted to messages can't be read
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
+    if cli.prefix.is_some() {
+        // Without it messages that don't mention the bot have no content to find commands in
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
+    let prefix_options = poise::PrefixFrameworkOptions {
+        // Mentioning the bot works as a prefix only when text commands were asked for
+        mention_as_prefix: cli.prefix.is_some(),
+        prefix: cli.prefix.clone(),
+        ..Default::default()
+    };
+    let framework = poise::Framework::builder()
+        .options(poise::FrameworkOptions {
+            commands: commands(),
+            event_handler: |ctx, event, _framework, data| Box::pin(event_handler(ctx, event, data)),
+            on_error: |error| Box::pin(on_error(error)),
+            prefix_options,
+            pre_command: |ctx| {
+                Box::pin(async move {
+                    ctx.data().metrics.command(&ctx.command().qualified_name);
+                    log_command(ctx).await;
+                    info!(
+                        command = %ctx.command().qualified_name,
+                        user = %ctx.author().id,
+                        guild = ?ctx.guild_id().map(|guild| guild.0),
+                        "Command invoked"
+                    );
+                })
+            },
+            ..Default::default()
+        })
+        .token(token)
+        .intents(intents)
+        .setup(move |ctx, ready, framework| {
+            Box::pin(async move {
+                info!("Connected as {}", ready.user.name);
+                register(
+                    &ctx.http,
+                    &framework.options().commands,
+                    &guilds,
+                    register_globally,
+                )
+                .await?;
+                Ok(data)
+            })
+        })
+        .build()
+        .await;
+    let framework = match framework {
+        Ok(framework) => framework,
+        Err(e) => {
+            error!("Couldn't set up the bot: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    tokio::spawn(purge_deleted_quotes(
+        database.clone(),
+        cli.keep_deleted_days,
+    ));
+    let http = framework.client().cache_and_http.http.clone();
+    let daily = framework.clone();
+    tokio::spawn(async move {
+        // The data is only handed over to the framework once the bot connects
+        post_quotes_of_the_day(http, daily.user_data().await, quote_of_the_day_time).await;
+    });
+    let shard_manager = framework.shard_manager().clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down");
+        shard_manager.lock().await.shutdown_all().await;
+    });
+    if let Err(e) = framework.start().await {
+        error!("Bot stopped with an error: {}", e);
+    }
+    // Let in-flight queries finish before exiting
+    database.close().await;
+    info!("Shut down cleanly");
+}
+
+/// Log errors of commands and tell whoever invoked them that it failed, instead of leaving them
+/// waiting for a response. Other kinds of errors get poise's default handling.
+async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    match error {
+        poise::FrameworkError::Command { error, ctx } => {
+            // Failed checks and arguments that don't parse are the invoker's mistake, not the bot's
+            ctx.data().metrics.error();
+            error!(
+                command = %ctx.command().qualified_name,
+                error = %error,
+                "Command failed"
+            );
+            let response = match &error {
+                BotError::Malformed(_) => "A stored quote is damaged and couldn't be used",
+                BotError::Database(_) => {
+                    "Couldn't access the quote database, please try again later"
+                }
+                BotError::Discord(_) => "Couldn't reach Discord, please try again later",
+                BotError::Invalid(message) => message,
+                _ => "Something went wrong, please try again later",
+            };
+            if let Err(e) = ctx.send(|f| f.content(response).ephemeral(true)).await {
+                error!(error = %e, "Couldn't report a failed command");
+            }
+        }
+        poise::FrameworkError::CommandCheckFailed { error: None, ctx } => {
+            let response = "Only moderators can do that, ask someone with the Manage Messages \
+                permission or the moderator role";
+            if let Err(e) = ctx.send(|f| f.content(response).ephemeral(true)).await {
+                error!(error = %e, "Couldn't report a failed check");
+            }
+        }
+        error => {
+            if let Err(e) = poise::builtins::on_error(error).await {
+                error!(error = %e, "Couldn't handle an error");
+            }
+        }
+    }
+}
+
+/// The columns of a stored quote needed to show it.
+struct StoredQuote {
+    id: i64,
+    /// None for quotes by someone given only by name, see `Author::Named`.
+    user_id: Option<String>,
+    username: Option<String>,
+    quote: String,
+    quote_date: String,
+    created_at: Option<NaiveDateTime>,
+    added_by: Option<String>,
+    guild_id: Option<String>,
+    channel_id: Option<String>,
+    message_id: Option<String>,
+    image_url: Option<String>,
+    image_channel_id: Option<String>,
+    image_message_id: Option<String>,
+}
+
+impl StoredQuote {
+    /// How the quote is shown, in the style of `style`.
+    fn shown(&self, style: &QuoteStyle) -> Result<ShownQuote<'_>, Error> {
+        let author = parse_author(self.user_id.as_deref())?;
+        let added_by = if let Some(added_by) = &self.added_by {
+            let added_by = added_by
+                .parse()
+                .map_err(|_| DatabaseError::MalformedEntry)?;
+            Some(serenity::UserId(added_by))
+        } else {
+            None
+        };
+        let link = message_link(
+            self.guild_id.as_deref(),
+            self.channel_id.as_deref(),
+            self.message_id.as_deref(),
+        )?;
+        let image_message = image_message(
+            self.image_channel_id.as_deref(),
+            self.image_message_id.as_deref(),
+        )?;
+        Ok(ShownQuote {
+            id: self.id,
+            author,
+            username: self.username.as_deref(),
+            quote: &self.quote,
+            date: quote_day(&self.quote_date, self.created_at, style),
+            code_block: style.code_blocks,
+            added_by,
+            link,
+            image_url: self.image_url.clone(),
+            image_message,
+        })
+    }
+}
+
+/// A stored quote, as it's shown to users.
+struct ShownQuote<'a> {
+    id: i64,
+    /// None if the quote is by someone given only by name.
+    author: Option<serenity::UserId>,
+    /// The author's name when the quote was added, shown if they can't be looked up anymore or
+    /// were only given by name.
+    username: Option<&'a str>,
+    quote: &'a str,
+    date: String,
+    /// Whether the quote is shown verbatim in a code block.
+    code_block: bool,
+    added_by: Option<serenity::UserId>,
+    /// Jump link to the message the quote was saved from.
+    link: Option<String>,
+    /// Image shown along with the quote, like a screenshot of it.
+    image_url: Option<String>,
+    /// Channel and message the image is attached to, if it's in one.
+    image_message: Option<(serenity::ChannelId, serenity::MessageId)>,
+}
+
+/// Reply with `shown`, as an embed if possible, with buttons to vote on it.
+async fn show_quote(ctx: Context<'_>, mut shown: ShownQuote<'_>) -> Result<(), Error> {
+    shown.image_url = fresh_image_url(ctx, &ctx.data().database, &shown).await;
+    let id = shown.id;
+    let (upvotes, downvotes) = vote_counts(&ctx.data().database, id).await?;
+    if can_embed(ctx) {
+        // Users that deleted their account can't be looked up, those just get their name or id
+        let user = if let Some(author) = shown.author {
+            author.to_user(ctx).await.ok()
+        } else {
+            None
+        };
+        ctx.send(|f| {
+            f.embed(|e| quote_embed(e, &shown, user.as_ref()))
+                .allowed_mentions(|m| mention_only(m, shown.author))
+                .components(|c| vote_buttons(c, id, upvotes, downvotes))
+        })
+        .await?;
+    } else {
+        // Mentions of members that left render as an unknown user, so show their old name instead
+        let is_member = match (ctx.guild_id(), shown.author) {
+            (Some(guild), Some(author)) => guild.member(ctx, author).await.is_ok(),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        let mut response = serenity::MessageBuilder::new();
+        if shown.code_block {
+            response.push_codeblock_safe(break_code_fences(shown.quote), None);
+        } else {
+            push_quote_text(&mut response, shown.quote, false).push("\n");
+        }
+        match (is_member, shown.author, shown.username) {
+            (false, _, Some(username)) | (_, None, Some(username)) => {
+                response.push_safe(escape_markdown(username))
+            }
+            (_, Some(author), _) => response.mention(&author),
+            (_, None, None) => return Err(DatabaseError::MalformedEntry.into()),
+        };
+        response
+            .push(" on ")
+            .push_safe(&shown.date)
+            .push(format!(" (#{})", id));
+        if let Some(added_by) = shown.added_by {
+            response.push(", submitted by ").mention(&added_by);
+        }
+        if let Some(link) = &shown.link {
+            response.push("\n").push(link);
+        }
+        if let Some(image_url) = &shown.image_url {
+            if image_expired(image_url, Utc::now()) {
+                response.push("\n(the image expired)");
+            } else {
+                response.push("\n").push(image_url);
+            }
+        }
+        let response = response.build();
+        ctx.send(|f| {
+            f.content(response)
+                .allowed_mentions(|m| mention_only(m, shown.author))
+                .components(|c| vote_buttons(c, id, upvotes, downvotes))
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Show quotes verbatim in code blocks, so markdown in them isn't rendered
+#[poise::command(slash_command, guild_only, rename = "code-blocks")]
+async fn code_blocks(
+    ctx: Context<'_>,
+    #[description = "Whether quotes are shown in code blocks"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    query!(
+        "INSERT INTO guild_settings (guild_id, code_blocks) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET code_blocks = excluded.code_blocks;",
+        guild_id,
+        enabled
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the code blocks setting");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let response = if enabled {
+        "Quotes are now shown in code blocks"
+    } else {
+        "Quotes are now shown in bold"
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Post the quotes added on the same day in earlier years with the quote of the day
+#[poise::command(slash_command, guild_only)]
+async fn anniversaries(
+    ctx: Context<'_>,
+    #[description = "Whether to post them"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    query!(
+        "INSERT INTO guild_settings (guild_id, anniversaries) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET anniversaries = excluded.anniversaries;",
+        guild_id,
+        enabled
+    )
+    .execute(&ctx.data().database)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the anniversaries setting");
+        e
+    })?;
+    ctx.data().refresh_settings(&guild_id).await?;
+    let response = if !enabled {
+        "Quotes from this day in earlier years won't be posted anymore"
+    } else if ctx
+        .data()
+        .settings(&guild_id)
+        .quote_of_the_day_channel
+        .is_some()
+    {
+        "Quotes from this day in earlier years will be posted with the quote of the day"
+    } else {
+        "Quotes from this day in earlier years will be posted once a quote of the day channel is \
+        set with /config quote-of-the-day"
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Log which commands are used in this server for /quote usage, or stop and forget what was logged
+#[poise::command(slash_command, guild_only, rename = "usage-log")]
+async fn usage_log(
+    ctx: Context<'_>,
+    #[description = "Whether to log them"] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = guild_id(ctx)?;
+    set_usage_log(ctx.data(), &guild_id, enabled).await?;
+    let response = if enabled {
+        format!(
+            "Commands used in this server will be logged for /quote usage, for {} days each",
+            USAGE_LOG_DAYS
+        )
+    } else {
+        "Commands used in this server aren't logged anymore, and what was logged is gone"
+            .to_string()
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// Turn logging commands in the guild with id `guild_id` on or off, forgetting what was logged when
+/// turning it off.
+async fn set_usage_log(data: &Data, guild_id: &str, enabled: bool) -> Result<(), Error> {
+    let mut transaction = data.database.begin().await?;
+    query!(
+        "INSERT INTO guild_settings (guild_id, usage_log) VALUES (?,?)
+        ON CONFLICT (guild_id) DO UPDATE SET usage_log = excluded.usage_log;",
+        guild_id,
+        enabled
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't save the usage log setting");
+        e
+    })?;
+    if !enabled {
+        query!("DELETE FROM command_log WHERE guild_id = ?;", guild_id)
+            .execute(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    data.refresh_settings(guild_id).await
+}
+
+/// Post a random quote in every configured channel at `time` each day, for as long as the bot
+/// runs.
+async fn post_quotes_of_the_day(http: Arc<serenity::Http>, data: &Data, time: NaiveTime) {
+    loop {
+        tokio::time::sleep(until_next(time)).await;
+        let channels = query!(
+            r#"SELECT guild_id, quote_of_the_day_channel as "channel!", timezone, date_format,
+            code_blocks, anniversaries FROM guild_settings
+            WHERE quote_of_the_day_channel IS NOT NULL;"#
+        )
+        .fetch_all(&data.database)
+        .await;
+        let channels = match channels {
+            Ok(channels) => channels,
+            Err(e) => {
+                error!(error = %e, "Couldn't fetch quote of the day channels");
+                continue;
+            }
+        };
+        for entry in channels {
+            let style = QuoteStyle::new(
+                entry.timezone.as_deref(),
+                entry.date_format.as_deref(),
+                entry.code_blocks,
+            );
+            if let Err(e) =
+                post_quote_of_the_day(&http, data, &entry.guild_id, &entry.channel, &style).await
+            {
+                error!(error = %e, guild = %entry.guild_id, "Couldn't post the quote of the day");
+            }
+            if !entry.anniversaries.unwrap_or(false) {
+                continue;
+            }
+            if let Err(e) = post_anniversaries(
+                &http,
+                &data.database,
+                &entry.guild_id,
+                &entry.channel,
+                &style,
+            )
+            .await
+            {
+                error!(error = %e, guild = %entry.guild_id, "Couldn't post the anniversaries");
+            }
+        }
+    }
+}
+
+/// Post the quotes added on this day in earlier years, if there are any.
+async fn post_anniversaries(
+    http: &Arc<serenity::Http>,
+    database: &Pool<Sqlite>,
+    guild_id: &str,
+    channel_id: &str,
+    style: &QuoteStyle,
+) -> Result<(), Error> {
+    let channel = serenity::ChannelId(
+        channel_id
+            .parse()
+            .map_err(|_| DatabaseError::MalformedEntry)?,
+    );
+    let today = Utc::now().with_timezone(&style.timezone).date_naive();
+    let entries = anniversary_quotes(database, guild_id, today).await?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let (content, authors) = anniversary_message(&entries, style)?;
+    channel
+        .send_message(http, |m| {
+            m.content(content)
+                .allowed_mentions(|m| mention_only(m, authors))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Post a quote of the guild with id `guild_id` in the channel with id `channel_id`, picked like
+/// /quote random picks them, so not one shown there recently.
+async fn post_quote_of_the_day(
+    http: &Arc<serenity::Http>,
+    data: &Data,
+    guild_id: &str,
+    channel_id: &str,
+    style: &QuoteStyle,
+) -> Result<(), Error> {
+    let channel = serenity::ChannelId(
+        channel_id
+            .parse()
+            .map_err(|_| DatabaseError::MalformedEntry)?,
+    );
+    let entries = random_quotes(
+        data,
+        guild_id,
+        channel,
+        &QuoteFilter::default(),
+        1,
+        RandomMode::default(),
+    )
+    .await?;
+    let body = if let Some(body) = entries.first() {
+        body
+    } else {
+        return Ok(());
+    };
+    data.remember_recent_quote(channel, body.id);
+    let mut shown = body.shown(style)?;
+    shown.image_url = fresh_image_url(http, &data.database, &shown).await;
+    let author = shown.author;
+    let user = if let Some(author) = author {
+        author.to_user(http).await.ok()
+    } else {
+        None
+    };
+    channel
+        .send_message(http, |m| {
+            m.content("Quote of the day")
+                .embed(|e| quote_embed(e, &shown, user.as_ref()))
+                .allowed_mentions(|m| mention_only(m, author))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Every hour, permanently remove quotes deleted more than `keep_days` days ago, and commands logged
+/// more than `USAGE_LOG_DAYS` days ago.
+async fn purge_deleted_quotes(database: Pool<Sqlite>, keep_days: u32) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let logged_before = Utc::now() - chrono::Duration::days(USAGE_LOG_DAYS.into());
+        if let Err(e) = query!(
+            "DELETE FROM command_log WHERE invoked_at < ?;",
+            logged_before
+        )
+        .execute(&database)
+        .await
+        {
+            error!(error = %e, "Couldn't purge the command log");
+        }
+        let cutoff = Utc::now() - chrono::Duration::days(keep_days.into());
+        match query!("DELETE FROM quotes WHERE deleted_at < ?;", cutoff)
+            .execute(&database)
+            .await
+        {
+            Ok(result) if result.rows_affected() > 0 => {
+                info!(count = result.rows_affected(), "Purged deleted quotes");
+            }
+            Ok(_) => {}
+            Err(e) => error!(error = %e, "Couldn't purge deleted quotes"),
+        }
+    }
+}
+
+/// `uptime` as days, hours and minutes, like `2d 5h 13m`.
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    format!(
+        "{}d {}h {}m",
+        minutes / (60 * 24),
+        minutes / 60 % 24,
+        minutes % 60
+    )
+}
+
+/// How long until it's next `time` o'clock in UTC.
+fn until_next(time: NaiveTime) -> Duration {
+    let now = Utc::now().naive_utc();
+    let mut next = now.date().and_time(time);
+    if next <= now {
+        next += chrono::Duration::days(1);
+    }
+    (next - now).to_std().unwrap_or_default()
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|e| format!("{}, expected a time like 18:30", e))
+}
+
+async fn event_handler(
+    ctx: &serenity::Context,
+    event: &poise::Event<'_>,
+    data: &Data,
+) -> Result<(), Error> {
+    match event {
+        poise::Event::InteractionCreate {
+            interaction: serenity::Interaction::MessageComponent(press),
+        } => {
+            if let Some((id, vote)) = parse_vote_button(&press.data.custom_id) {
+                record_vote(ctx, data, press, id, vote).await?;
+            }
+        }
+        poise::Event::ReactionAdd { add_reaction } => {
+            save_reacted_message(ctx, data, add_reaction).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Save the message `reaction` was added to as a quote, if that made it reach the threshold set
+/// for its guild. Guilds with /config moderation on get it as a pending quote instead.
+async fn save_reacted_message(
+    ctx: &serenity::Context,
+    data: &Data,
+    reaction: &serenity::Reaction,
+) -> Result<(), Error> {
+    let guild_id = if let Some(guild_id) = reaction.guild_id {
+        guild_id.as_u64().to_string()
+    } else {
+        return Ok(());
+    };
+    let settings = data.settings(&guild_id);
+    let (emoji, threshold) = if let (Some(emoji), Some(threshold)) =
+        (settings.reaction_emoji, settings.reaction_threshold)
+    {
+        (emoji, threshold)
+    } else {
+        return Ok(());
+    };
+    if reaction.emoji.to_string() != emoji {
+        return Ok(());
+    }
+    let channel_id = reaction.channel_id.as_u64().to_string();
+    let message_id = reaction.message_id.as_u64().to_string();
+    // Every further reaction gets here too, so skip fetching the message for those
+    let saved = query!(
+        r#"SELECT EXISTS (SELECT 1 FROM quotes WHERE message_id = ?1
+        AND (guild_id = ?2 OR guild_id IS NULL) AND deleted_at IS NULL)
+        OR EXISTS (SELECT 1 FROM pending_quotes WHERE message_id = ?1 AND guild_id = ?2)
+        as "saved!: bool";"#,
+        message_id,
+        guild_id
+    )
+    .fetch_one(&data.database)
+    .await?
+    .saved;
+    if saved {
+        return Ok(());
+    }
+    // The cache doesn't follow reactions, so they have to be counted on a freshly fetched message
+    // unless one is enough
+    let cached = if threshold <= 1 {
+        ctx.cache.message(reaction.channel_id, reaction.message_id)
+    } else {
+        None
+    };
+    let message = match cached {
+        Some(message) => message,
+        None => reaction.message(ctx).await?,
+    };
+    if threshold > 1 {
+        let count = message
+            .reactions
+            .iter()
+            .find(|counted| counted.reaction_type.to_string() == emoji)
+            .map(|counted| counted.count as i64)
+            .unwrap_or(0);
+        if count < threshold {
+            return Ok(());
+        }
+    }
+    if message.author.bot {
+        return Ok(());
+    }
+    let user_id = message.author.id.as_u64().to_string();
+    if invalid_quote(data, &guild_id, &message.content).is_some()
+        || opted_out(&data.database, &user_id).await?
+    {
+        return Ok(());
+    }
+    // Saved from another message, so looking up the name can be skipped too
+    let existing = query!(
+        "SELECT id FROM quotes WHERE user_id = ? AND quote = ?
+        AND (guild_id = ? OR guild_id IS NULL) AND deleted_at IS NULL;",
+        user_id,
+        message.content,
+        guild_id
+    )
+    .fetch_optional(&data.database)
+    .await?;
+    if existing.is_some() {
+        return Ok(());
+    }
+    let username = if let Some(guild) = reaction.guild_id {
+        display_name(ctx, &message.author, guild).await
+    } else {
+        message.author.name.clone()
+    };
+    let source = Some((channel_id.as_str(), message_id.as_str()));
+    let image = message_image(&message).map(|url| QuoteImage {
+        url,
+        message: source,
+    });
+    if settings.moderation.unwrap_or(false) {
+        // Whoever's reaction reached the threshold is told once a moderator reviews it
+        let added_by = if let Some(user) = reaction.user_id {
+            user.as_u64().to_string()
+        } else {
+            return Ok(());
+        };
+        let id = insert_pending_quote(
+            &data.database,
+            &guild_id,
+            Some(&user_id),
+            &username,
+            &message.content,
+            &[],
+            &added_by,
+            source,
+            image,
+        )
+        .await?;
+        message
+            .reply(ctx, format!("Submitted as pending quote #{}", id))
+            .await?;
+        return Ok(());
+    }
+    let mut transaction = data.database.begin().await?;
+    let insertion = insert_quote(
+        &mut transaction,
+        &guild_id,
+        Some(&user_id),
+        &username,
+        &message.content,
+        None,
+        source,
+        image,
+    )
+    .await?;
+    let id = if let Insertion::Added(id) = insertion {
+        id
+    } else {
+        return Ok(());
+    };
+    transaction.commit().await?;
+    data.forget_quote_ids();
+    message
+        .reply(ctx, format!("Saved as quote #{}", id))
+        .await?;
+    Ok(())
+}
+
+/// Store a vote from a button under a quote, replacing any earlier vote by the same user, and
+/// update the counts on the buttons.
+async fn record_vote(
+    ctx: &serenity::Context,
+    data: &Data,
+    press: &serenity::MessageComponentInteraction,
+    id: i64,
+    vote: i64,
+) -> Result<(), Error> {
+    let voter_id = press.user.id.as_u64().to_string();
+    let stored = match store_vote(&data.database, id, &voter_id, vote).await {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!(error = %e, quote = id, "Couldn't record vote");
+            false
+        }
+    };
+    if !stored {
+        press
+            .create_interaction_response(ctx, |r| {
+                r.interaction_response_data(|d| {
+                    d.content("Couldn't vote on that quote, it may have been deleted")
+                        .ephemeral(true)
+                })
+            })
+            .await?;
+        return Ok(());
+    }
+    let (upvotes, downvotes) = vote_counts(&data.database, id).await?;
+    press
+        .create_interaction_response(ctx, |r| {
+            r.kind(serenity::InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| {
+                    d.components(|c| vote_buttons(c, id, upvotes, downvotes))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Store `vote` by the user with id `voter_id` on quote `id`, replacing their earlier one. Returns
+/// whether it was stored, which it isn't once the quote was deleted, even if it can still be
+/// restored.
+async fn store_vote(
+    database: &Pool<Sqlite>,
+    id: i64,
+    voter_id: &str,
+    vote: i64,
+) -> Result<bool, Error> {
+    let stored = query!(
+        "INSERT INTO votes (quote_id, voter_id, vote)
+        SELECT id, ?2, ?3 FROM quotes WHERE id = ?1 AND deleted_at IS NULL
+        ON CONFLICT (quote_id, voter_id) DO UPDATE SET vote = excluded.vote;",
+        id,
+        voter_id,
+        vote
+    )
+    .execute(database)
+    .await?
+    .rows_affected();
+    Ok(stored > 0)
+}
+
+/// Number of up and down votes quote `id` has.
+async fn vote_counts(database: &Pool<Sqlite>, id: i64) -> Result<(i64, i64), Error> {
+    let counts = query!(
+        r#"SELECT COALESCE(SUM(vote > 0), 0) as "up!: i64", COALESCE(SUM(vote < 0), 0) as "down!: i64"
+        FROM votes WHERE quote_id = ?;"#,
+        id
+    )
+    .fetch_one(database)
+    .await?;
+    Ok((counts.up, counts.down))
+}
+
+/// Up and down vote buttons for quote `id`, labeled with its current vote counts.
+fn vote_buttons(
+    c: &mut serenity::CreateComponents,
+    id: i64,
+    upvotes: i64,
+    downvotes: i64,
+) -> &mut serenity::CreateComponents {
+    c.create_action_row(|r| {
+        r.create_button(|b| {
+            b.custom_id(format!("vote:{}:up", id))
+                .emoji('👍')
+                .label(upvotes)
+                .style(serenity::ButtonStyle::Secondary)
+        })
+        .create_button(|b| {
+            b.custom_id(format!("vote:{}:down", id))
+                .emoji('👎')
+                .label(downvotes)
+                .style(serenity::ButtonStyle::Secondary)
+        })
+    })
+}
+
+/// The quote id and vote of a button made by `vote_buttons`.
+fn parse_vote_button(custom_id: &str) -> Option<(i64, i64)> {
+    let mut parts = custom_id.split(':');
+    if parts.next()? != "vote" {
+        return None;
+    }
+    let id = parts.next()?.parse().ok()?;
+    let vote = match parts.next()? {
+        "up" => 1,
+        "down" => -1,
+        _ => return None,
+    };
+    Some((id, vote))
+}
+
+/// Every command of the bot.
+fn commands() -> Vec<poise::Command<Data, Error>> {
+    let mut quote = quote();
+    for subcommand in &mut quote.subcommands {
+        if subcommand.name == "random" {
+            subcommand.prefix_action = random_text().prefix_action;
+        }
+    }
+    let mut commands = vec![quote, quote_message(), config(), ping(), uptime()];
+    localization::localize(&mut commands, None);
+    commands
+}
+
+/// Register `commands` in each of `guilds`, and globally if that didn't work in any of them or
+/// `register_globally` is set.
+async fn register(
+    http: &serenity::Http,
+    commands: &[poise::Command<Data, Error>],
+    guilds: &[u64],
+    register_globally: bool,
+) -> Result<(), Error> {
+    let mut registered_in_guild = false;
+    for &guild in guilds {
+        // A wrong testing guild shouldn't stop the bot, the commands are registered globally
+        // instead
+        match poise::builtins::register_in_guild(http, commands, serenity::GuildId(guild)).await {
+            Ok(()) => {
+                registered_in_guild = true;
+                info!("Registered commands in guild {}", guild);
+            }
+            Err(e) => warn!(error = %e, "Couldn't register commands in guild {}", guild),
+        }
+    }
+    if !registered_in_guild || register_globally {
+        poise::builtins::register_globally(http, commands).await?;
+        info!("Registered commands globally");
+    }
+    Ok(())
+}
+
+/// Register the bot's commands like it does when starting, then list them.
+async fn register_only(token: &str, guilds: &[u64], register_globally: bool) -> Result<(), Error> {
+    let http = serenity::Http::new(token);
+    let application = http.get_current_application_info().await?;
+    http.set_application_id(application.id.0);
+    let commands = commands();
+    register(&http, &commands, guilds, register_globally).await?;
+    for name in command_names(&commands) {
+        info!("Registered {}", name);
+    }
+    Ok(())
+}
+
+/// How users invoke each of `commands` in Discord, subcommands included.
+fn command_names(commands: &[poise::Command<Data, Error>]) -> Vec<String> {
+    let mut names = Vec::new();
+    for command in commands {
+        if let (Some(name), Some(_)) = (command.context_menu_name, command.context_menu_action) {
+            names.push(name.to_string());
+        }
+        if command.slash_action.is_none() {
+            continue;
+        }
+        if command.subcommands.is_empty() {
+            names.push(format!("/{}", command.name));
+        }
+        for subcommand in &command.subcommands {
+            if subcommand.slash_action.is_some() {
+                names.push(format!("/{} {}", command.name, subcommand.name));
+            }
+        }
+    }
+    names
+}
+
+/// Remove every slash command of the bot with `token`, globally and in each of `guilds`.
+async fn unregister(token: &str, guilds: &[u64]) -> Result<(), Error> {
+    let http = serenity::Http::new(token);
+    let application = http.get_current_application_info().await?;
+    http.set_application_id(application.id.0);
+    serenity::Command::set_global_application_commands(&http, |commands| commands).await?;
+    info!("Unregistered global commands");
+    for &guild in guilds {
+        serenity::GuildId(guild)
+            .set_application_commands(&http, |commands| commands)
+            .await?;
+        info!("Unregistered commands in guild {}", guild);
+    }
+    Ok(())
+}
+
+/// Resolves once the process is asked to stop, either by Ctrl-C or (on unix) SIGTERM from e.g. systemd.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Couldn't listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Split a comma separated list of tags into lowercase tags, dropping empty and repeated ones.
+fn parse_tags(tags: &str) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags.split(',') {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    parsed
+}
+
+/// Turn user input into an FTS5 query matching quotes that have every word in it as a prefix of one
+/// of theirs. Quoting each word keeps FTS5 syntax in the input from being interpreted. Returns
+/// `None` if there are no words.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Parse a day given as year-month-day, or explain why it isn't one.
+fn parse_day(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|_| {
+        format!(
+            "{} isn't a valid date, use year-month-day like 2023-12-25",
+            date
+        )
+    })
+}
+
+/// Explains why `quote` can't be stored, if it can't.
+fn invalid_quote(data: &Data, guild_id: &str, quote: &str) -> Option<String> {
+    let max_length = data.max_quote_length(guild_id);
+    if quote.trim().is_empty() {
+        Some("Quotes can't be empty".to_string())
+    } else if quote.chars().count() > max_length {
+        Some(format!(
+            "Quotes can't be longer than {} characters",
+            max_length
+        ))
+    } else {
+        None
+    }
+}
+
+/// Who a quote is by.
+#[derive(Clone, Copy)]
+enum Author<'a> {
+    User(&'a serenity::User),
+    /// Someone or something given only by name, like a person who left the server or a book.
+    /// Stored with that name and without a user id.
+    Named(&'a str),
+}
+
+/// Store `quote` as said by `author` and tell the invoking user about it, unless the exact same
+/// quote (compared case-sensitively) or the same message is already stored in this guild or the
+/// quote is invalid. `source` is the message it was saved from, if any, and `image_url` an image to
+/// show with it, the first one attached to `source` if not given.
+async fn save_quote(
+    ctx: Context<'_>,
+    author: Author<'_>,
+    quote: &str,
+    tags: &[String],
+    source: Option<&serenity::Message>,
+    image_url: Option<&str>,
+) -> Result<(), Error> {
+    let source_image = source.and_then(message_image);
+    let guild_id = guild_id(ctx)?;
+    if let Some(problem) = invalid_quote(ctx.data(), &guild_id, quote) {
+        ctx.send(|f| f.content(problem).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let (user_id, name) = match author {
+        Author::User(user) => (Some(user.id.as_u64().to_string()), user.name.as_str()),
+        Author::Named(name) => (None, name),
+    };
+    let source = source.map(|message| {
+        (
+            message.channel_id.as_u64().to_string(),
+            message.id.as_u64().to_string(),
+        )
+    });
+    // One attached to the quoted message can be fetched from it again once its link expires
+    let image = match (image_url, source_image, &source) {
+        (Some(url), _, _) => Some(QuoteImage { url, message: None }),
+        (None, Some(url), Some((channel_id, message_id))) => Some(QuoteImage {
+            url,
+            message: Some((channel_id, message_id)),
+        }),
+        _ => None,
+    };
+    if let Some(user_id) = &user_id {
+        if opted_out(&ctx.data().database, user_id).await? {
+            let response = format!("{} asked not to be quoted", name);
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        }
+    }
+    if let Some(wait) = ctx.data().add_cooldown(&guild_id, ctx.author().id) {
+        let response = format!(
+            "Slow down, you can add another quote in {} second(s)",
+            wait.as_secs() + 1
+        );
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let added_by = ctx.author().id.as_u64().to_string();
+    let username = match (author, ctx.guild_id()) {
+        (Author::User(user), Some(guild)) => display_name(ctx, user, guild).await,
+        _ => name.to_string(),
+    };
+    if ctx.data().settings(&guild_id).moderation.unwrap_or(false) && !is_moderator(ctx).await? {
+        let id = insert_pending_quote(
+            &ctx.data().database,
+            &guild_id,
+            user_id.as_deref(),
+            &username,
+            quote,
+            tags,
+            &added_by,
+            source
+                .as_ref()
+                .map(|(channel_id, message_id)| (channel_id.as_str(), message_id.as_str())),
+            image,
+        )
+        .await?;
+        ctx.data().note_added(&guild_id, ctx.author().id);
+        let response = format!(
+            "Quote submitted as pending quote #{}, you'll get a message once a moderator reviews it",
+            id
+        );
+        ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        return Ok(());
+    }
+    // The quote and its tags are stored together or not at all
+    let mut transaction = ctx.data().database.begin().await?;
+    let insertion = insert_quote(
+        &mut transaction,
+        &guild_id,
+        user_id.as_deref(),
+        &username,
+        quote,
+        Some(&added_by),
+        source
+            .as_ref()
+            .map(|(channel_id, message_id)| (channel_id.as_str(), message_id.as_str())),
+        image,
+    )
+    .await?;
+    let id = match insertion {
+        Insertion::Added(id) => id,
+        Insertion::Duplicate(existing) => {
+            let response = format!("That quote already exists as #{}", existing);
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    for tag in tags {
+        query!("INSERT INTO tags (quote_id, tag) VALUES (?,?);", id, tag)
+            .execute(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    ctx.data().forget_quote_ids();
+    ctx.data().note_added(&guild_id, ctx.author().id);
+    let mut response = serenity::MessageBuilder::new();
+    response.push(format!("Quote #{}: ", id));
+    push_quote_text(
+        &mut response,
+        quote,
+        ctx.data().quote_style(&guild_id).code_blocks,
+    )
+    .push(", by ")
+    .push_safe(escape_markdown(name))
+    .push(" added!");
+    let response = response.build();
+    let mentioned = match author {
+        Author::User(user) => Some(user.id),
+        Author::Named(_) => None,
+    };
+    reply_with_image(ctx, id, response, mentioned, image).await
+}
+
+/// Reply with `response` to adding the quote with id `id`, mentioning only `mentioned`. Links to
+/// uploaded images expire within a day, so an `image` that isn't attached to a message the bot can
+/// get a new link from is posted again with the reply, and the quote shows that copy instead.
+async fn reply_with_image(
+    ctx: Context<'_>,
+    id: i64,
+    response: String,
+    mentioned: Option<serenity::UserId>,
+    image: Option<QuoteImage<'_>>,
+) -> Result<(), Error> {
+    let repost = image
+        .filter(|image| image.message.is_none() && !image_expired(image.url, Utc::now()))
+        .filter(|image| is_attachment_link(image.url))
+        .and_then(|image| url::Url::parse(image.url).ok())
+        .map(serenity::AttachmentType::Image);
+    let sent = ctx
+        .send(|f| {
+            f.content(&response)
+                .allowed_mentions(|m| mention_only(m, mentioned));
+            if let Some(repost) = &repost {
+                f.attachment(repost.clone());
+            }
+            f
+        })
+        .await;
+    let reply = match sent {
+        Ok(reply) => reply,
+        // Like when the bot can't attach files here, the quote is added all the same
+        Err(e) if repost.is_some() => {
+            warn!(error = %e, quote = id, "Couldn't post a quote's image again");
+            ctx.send(|f| {
+                f.content(response)
+                    .allowed_mentions(|m| mention_only(m, mentioned))
+            })
+            .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if repost.is_none() {
+        return Ok(());
+    }
+    let message = reply.message().await?;
+    if let Some(attachment) = message.attachments.first() {
+        let channel_id = message.channel_id.as_u64().to_string();
+        let message_id = message.id.as_u64().to_string();
+        query!(
+            "UPDATE quotes SET image_url = ?, image_channel_id = ?, image_message_id = ?
+            WHERE id = ?;",
+            attachment.url,
+            channel_id,
+            message_id,
+            id
+        )
+        .execute(&ctx.data().database)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Send `notice` to the user with id `user_id` in a direct message. They may not accept those, so
+/// failing is only logged.
+async fn notify_submitter(ctx: Context<'_>, user_id: &str, notice: String) {
+    let user = match user_id.parse() {
+        Ok(user) => serenity::UserId(user),
+        Err(_) => {
+            warn!(user = %user_id, "Couldn't notify a submitter with a malformed id");
+            return;
+        }
+    };
+    let sent = match user.create_dm_channel(ctx).await {
+        Ok(channel) => channel.say(ctx, notice).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        warn!(error = %e, user = %user, "Couldn't notify a submitter");
+    }
+}
+
+/// Whether the user with id `user_id` asked not to be quoted.
+async fn opted_out(database: &Pool<Sqlite>, user_id: &str) -> Result<bool, Error> {
+    let entry = query!("SELECT user_id FROM optout WHERE user_id = ?;", user_id)
+        .fetch_optional(database)
+        .await?;
+    Ok(entry.is_some())
+}
+
+/// Name `user` goes by in `guild`, their nickname if they have one.
+async fn display_name(
+    cache_http: impl serenity::CacheHttp,
+    user: &serenity::User,
+    guild: serenity::GuildId,
+) -> String {
+    user.nick_in(cache_http, guild)
+        .await
+        .unwrap_or_else(|| user.name.clone())
+}
+
+/// Result of `insert_quote`, with the id of the quote that was added or is already stored.
+enum Insertion {
+    Added(i64),
+    Duplicate(i64),
+}
+
+/// Image shown with a quote, like a screenshot of it.
+#[derive(Clone, Copy)]
+struct QuoteImage<'a> {
+    url: &'a str,
+    /// Ids of the channel and message the image is attached to, if it's in one, to get a new link
+    /// from once `url` expires.
+    message: Option<(&'a str, &'a str)>,
+}
+
+/// Queue `quote` and its `tags` for a moderator to approve, as with `insert_quote`, and return the
+/// id of the pending quote. `added_by` is told once it's approved or rejected.
+#[allow(clippy::too_many_arguments)]
+async fn insert_pending_quote(
+    database: &Pool<Sqlite>,
+    guild_id: &str,
+    user_id: Option<&str>,
+    username: &str,
+    quote: &str,
+    tags: &[String],
+    added_by: &str,
+    source: Option<(&str, &str)>,
+    image: Option<QuoteImage<'_>>,
+) -> Result<i64, Error> {
+    let tags = serde_json::to_string(tags)?;
+    let (channel_id, message_id) = source.unzip();
+    let image_url = image.map(|image| image.url);
+    let (image_channel_id, image_message_id) = image.and_then(|image| image.message).unzip();
+    let now = Utc::now();
+    let id = query!(
+        "INSERT INTO pending_quotes (guild_id, user_id, username, quote, tags, added_by,
+        channel_id, message_id, image_url, image_channel_id, image_message_id, created_at)
+        VALUES (?,?,?,?,?,?,?,?,?,?,?,?);",
+        guild_id,
+        user_id,
+        username,
+        quote,
+        tags,
+        added_by,
+        channel_id,
+        message_id,
+        image_url,
+        image_channel_id,
+        image_message_id,
+        now
+    )
+    .execute(database)
+    .await?
+    .last_insert_rowid();
+    Ok(id)
+}
+
+/// Store `quote` as said by the user with id `user_id` and name `username`, or by someone given
+/// only by that name without an id, dated today, unless the same quote (compared case-sensitively)
+/// or message is already stored in this guild. `added_by` is whoever submitted it, `source` the
+/// ids of the channel and message it was saved from and `image` one to show with it, if any.
+/// Meant to be run in a transaction, so that the check and the insert can't race another
+/// insert.
+#[allow(clippy::too_many_arguments)]
+async fn insert_quote(
+    connection: &mut SqliteConnection,
+    guild_id: &str,
+    user_id: Option<&str>,
+    username: &str,
+    quote: &str,
+    added_by: Option<&str>,
+    source: Option<(&str, &str)>,
+    image: Option<QuoteImage<'_>>,
+) -> Result<Insertion, Error> {
+    let (channel_id, message_id) = source.unzip();
+    let image_url = image.map(|image| image.url);
+    let (image_channel_id, image_message_id) = image.and_then(|image| image.message).unzip();
+    // A deleted copy would keep the quote from being stored, and is ignored by the duplicate check.
+    // Writing first also takes SQLite's write lock right away, instead of failing to upgrade a read
+    // lock later if another connection wrote in between.
+    // Quotes by someone given only by name are told apart by that name
+    query!(
+        "DELETE FROM quotes WHERE deleted_at IS NOT NULL
+        AND (guild_id = ?1 AND (user_id = ?2 OR user_id IS NULL AND ?2 IS NULL AND username = ?3)
+        AND quote = ?4 OR message_id = ?5);",
+        guild_id,
+        user_id,
+        username,
+        quote,
+        message_id
+    )
+    .execute(&mut *connection)
+    .await?;
+    let existing = query!(
+        "SELECT id FROM quotes
+        WHERE ((user_id = ?1 OR user_id IS NULL AND ?1 IS NULL AND username = ?2) AND quote = ?3
+        OR message_id = ?4) AND (guild_id = ?5 OR guild_id IS NULL) AND deleted_at IS NULL;",
+        user_id,
+        username,
+        quote,
+        message_id,
+        guild_id
+    )
+    .fetch_optional(&mut *connection)
+    .await?;
+    if let Some(existing) = existing {
+        return Ok(Insertion::Duplicate(existing.id));
+    }
+    let now = Utc::now();
+    let date = now.date_naive();
+    let id = query!(
+        "INSERT INTO quotes (user_id, username, quote_date, quote, created_at, added_by, guild_id,
+        channel_id, message_id, image_url, image_channel_id, image_message_id)
+        VALUES (?,?,?,?,?,?,?,?,?,?,?,?)",
+        user_id,
+        username,
+        date,
+        quote,
+        now,
+        added_by,
+        guild_id,
+        channel_id,
+        message_id,
+        image_url,
+        image_channel_id,
+        image_message_id,
+    )
+    .execute(&mut *connection)
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Couldn't insert quote");
+        e
+    })?
+    .last_insert_rowid();
+    Ok(Insertion::Added(id))
+}
+
+/// Check shared by the commands that delete or change quotes, which only moderators may use: members
+/// with the Manage Messages permission or the role set with /config moderator-role.
+async fn is_moderator(ctx: Context<'_>) -> Result<bool, Error> {
+    let member = if let Some(member) = ctx.author_member().await {
+        member
+    } else {
+        return Ok(false);
+    };
+    let role = ctx
+        .data()
+        .settings(&guild_id(ctx)?)
+        .moderator_role
+        .and_then(|role| role.parse().ok())
+        .map(serenity::RoleId);
+    if let Some(role) = role {
+        if member.roles.contains(&role) {
+            return Ok(true);
+        }
+    }
+    // Interactions come with the member's permissions, otherwise they're computed from the cache
+    let permissions = if let Some(permissions) = member.permissions {
+        permissions
+    } else {
+        member.permissions(ctx)?
+    };
+    Ok(permissions.manage_messages())
+}
+
+/// Id of the guild the command was invoked in, in the form it's stored in the database.
+fn guild_id(ctx: Context<'_>) -> Result<String, Error> {
+    let guild = ctx.guild_id().ok_or_else(|| {
+        BotError::Invalid("This command can only be used in a server".to_string())
+    })?;
+    Ok(guild.as_u64().to_string())
+}
+
+/// Fill in an embed showing `shown`, `user` being its author's profile if it could be fetched.
+/// Otherwise the author is shown by their stored name, or their id.
+fn quote_embed<'a>(
+    e: &'a mut serenity::CreateEmbed,
+    shown: &ShownQuote<'_>,
+    user: Option<&serenity::User>,
+) -> &'a mut serenity::CreateEmbed {
+    let mut description = serenity::MessageBuilder::new();
+    if shown.code_block {
+        description.push_codeblock_safe(break_code_fences(shown.quote), None);
+    } else {
+        push_escaped(&mut description, shown.quote);
+    }
+    let description = description.build();
+    e.description(description)
+        .footer(|f| f.text(format!("#{} on {}", shown.id, shown.date)));
+    if let Some(user) = user {
+        e.author(|a| a.name(&user.name).icon_url(user.face()));
+    } else if let Some(username) = shown.username {
+        e.author(|a| a.name(username));
+    } else if let Some(author) = shown.author {
+        e.author(|a| a.name(author));
+    }
+    if let Some(added_by) = shown.added_by {
+        e.field("Submitted by", added_by.mention(), true);
+    }
+    if let Some(link) = &shown.link {
+        e.field("Source", format!("[Jump to message]({})", link), true);
+    }
+    if let Some(image_url) = &shown.image_url {
+        // An expired image would show as broken, the message it came from may still have it
+        if image_expired(image_url, Utc::now()) {
+            e.field("Image", "Expired", true);
+        } else {
+            e.image(image_url);
+        }
+    }
+    e
+}
+
+/// Link to the message with `message_id` in `channel_id`, if the quote was saved from one.
+fn message_link(
+    guild_id: Option<&str>,
+    channel_id: Option<&str>,
+    message_id: Option<&str>,
+) -> Result<Option<String>, Error> {
+    let (channel_id, message_id) =
+        if let (Some(channel_id), Some(message_id)) = (channel_id, message_id) {
+            (channel_id, message_id)
+        } else {
+            return Ok(None);
+        };
+    let parse = |id: &str| id.parse().map_err(|_| DatabaseError::MalformedEntry);
+    let guild_id = guild_id.map(parse).transpose()?.map(serenity::GuildId);
+    let channel_id = serenity::ChannelId(parse(channel_id)?);
+    let message_id = serenity::MessageId(parse(message_id)?);
+    Ok(Some(message_id.link(channel_id, guild_id)))
+}
+
+/// Channel and message with `channel_id` and `message_id` an image of a quote is attached to, if
+/// it's in one.
+fn image_message(
+    channel_id: Option<&str>,
+    message_id: Option<&str>,
+) -> Result<Option<(serenity::ChannelId, serenity::MessageId)>, Error> {
+    let parse = |id: &str| id.parse().map_err(|_| DatabaseError::MalformedEntry);
+    match (channel_id, message_id) {
+        (Some(channel_id), Some(message_id)) => Ok(Some((
+            serenity::ChannelId(parse(channel_id)?),
+            serenity::MessageId(parse(message_id)?),
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// Link to the image shown with `shown`. Once the stored one expired, a new one is fetched from the
+/// message the image is attached to, if it's in one, and stored for the next time.
+async fn fresh_image_url(
+    http: impl AsRef<serenity::Http>,
+    database: &Pool<Sqlite>,
+    shown: &ShownQuote<'_>,
+) -> Option<String> {
+    let url = shown.image_url.clone()?;
+    let (channel, message) = match shown.image_message {
+        Some(image_message) if image_expired(&url, Utc::now()) => image_message,
+        _ => return Some(url),
+    };
+    let fresh = match channel.message(http, message).await {
+        Ok(message) => message_image(&message).map(str::to_string),
+        Err(e) => {
+            warn!(error = %e, quote = shown.id, "Couldn't fetch the message with a quote's image");
+            None
+        }
+    };
+    let fresh = if let Some(fresh) = fresh {
+        fresh
+    } else {
+        return Some(url);
+    };
+    if let Err(e) = query!(
+        "UPDATE quotes SET image_url = ? WHERE id = ?;",
+        fresh,
+        shown.id
+    )
+    .execute(database)
+    .await
+    {
+        error!(error = %e, quote = shown.id, "Couldn't store the new link to a quote's image");
+    }
+    Some(fresh)
+}
+
+/// Why `url` can't be shown as the image of a quote, if it can't. It has to be a web link to a file
+/// with an image extension, which is all Discord shows in embeds.
+fn invalid_image_url(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    let path = rest
+        .filter(|rest| !rest.is_empty() && !rest.contains(char::is_whitespace))
+        .map(|rest| rest.split(['?', '#']).next().unwrap_or(rest));
+    let path = if let Some(path) = path {
+        path
+    } else {
+        return Some("Image links have to start with https:// or http://".to_string());
+    };
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase());
+    if url.chars().count() > MAX_IMAGE_URL_LENGTH {
+        Some(format!(
+            "Image links can't be longer than {} characters",
+            MAX_IMAGE_URL_LENGTH
+        ))
+    } else if !matches!(
+        extension.as_deref(),
+        Some("png" | "jpg" | "jpeg" | "gif" | "webp")
+    ) {
+        Some("That link isn't to a png, jpg, gif or webp image".to_string())
+    } else {
+        None
+    }
+}
+
+/// Whether `url` links to a file attached to a Discord message.
+fn is_attachment_link(url: &str) -> bool {
+    [
+        "https://cdn.discordapp.com/attachments/",
+        "https://media.discordapp.net/attachments/",
+    ]
+    .iter()
+    .any(|prefix| url.starts_with(prefix))
+}
+
+/// Whether `url` is a Discord attachment link that stopped working before `now`. Those are signed
+/// with an expiry in their `ex` parameter, as a hexadecimal Unix timestamp.
+fn image_expired(url: &str, now: chrono::DateTime<Utc>) -> bool {
+    let discord = is_attachment_link(url);
+    let expiry = url
+        .split_once('?')
+        .and_then(|(_, query)| {
+            query
+                .split('&')
+                .find_map(|parameter| parameter.strip_prefix("ex="))
+        })
+        .and_then(|expiry| i64::from_str_radix(expiry, 16).ok());
+    match expiry {
+        Some(expiry) if discord => expiry < now.timestamp(),
+        _ => false,
+    }
+}
+
+/// Link to the first image attached to `message`, if it has one.
+fn message_image(message: &serenity::Message) -> Option<&str> {
+    message
+        .attachments
+        .iter()
+        .find(|attachment| is_image(attachment))
+        .map(|attachment| attachment.url.as_str())
+}
+
+/// Whether `attachment` is an image that can be shown with a quote.
+fn is_image(attachment: &serenity::Attachment) -> bool {
+    match &attachment.content_type {
+        Some(content_type) => content_type.starts_with("image/"),
+        None => invalid_image_url(&attachment.url).is_none(),
+    }
+}
+
+/// Whether the bot may post embeds in the channel the command was invoked in. When the cache doesn't
+/// know enough to tell, assumes it can.
+fn can_embed(ctx: Context<'_>) -> bool {
+    let guild = if let Some(guild) = ctx.guild() {
+        guild
+    } else {
+        return true;
+    };
+    let bot = ctx.serenity_context().cache.current_user_id();
+    let channel = guild
+        .channels
+        .get(&ctx.channel_id())
+        .and_then(|channel| channel.clone().guild());
+    if let (Some(member), Some(channel)) = (guild.members.get(&bot), channel) {
+        guild
+            .user_permissions_in(&channel, member)
+            .map(|permissions| permissions.embed_links())
+            .unwrap_or(true)
+    } else {
+        true
+    }
+}
+
+/// Whether the invoking member can read the history of `channel`, which also has to be in the
+/// guild the command was invoked in.
+async fn can_read_history(ctx: Context<'_>, channel: serenity::ChannelId) -> Result<bool, Error> {
+    let guild_id = if let Some(guild_id) = ctx.guild_id() {
+        guild_id
+    } else {
+        return Ok(false);
+    };
+    let mut channel =
+        if let Some(channel) = channel.to_channel(ctx.serenity_context()).await?.guild() {
+            channel
+        } else {
+            return Ok(false);
+        };
+    if channel.guild_id != guild_id {
+        return Ok(false);
+    }
+    // Threads have no permissions of their own, they're the ones of the channel they're in
+    if let (Some(_), Some(parent)) = (&channel.thread_metadata, channel.parent_id) {
+        if let Some(parent) = parent.to_channel(ctx.serenity_context()).await?.guild() {
+            channel = parent;
+        }
+    }
+    let (member, guild) = match (ctx.author_member().await, ctx.guild()) {
+        (Some(member), Some(guild)) => (member, guild),
+        _ => return Ok(false),
+    };
+    let permissions = guild.user_permissions_in(&channel, &member)?;
+    Ok(permissions.view_channel() && permissions.read_message_history())
+}
+
+/// Day a quote was added on, in the timezone and format of `style`. Quotes without a creation time
+/// only have the day they were added on, which is shown as it is, or unformatted if `style` needs
+/// a time of day.
+fn quote_day(quote_date: &str, created_at: Option<NaiveDateTime>, style: &QuoteStyle) -> String {
+    if let Some(created_at) = created_at {
+        return created_at
+            .and_utc()
+            .with_timezone(&style.timezone)
+            .format(&style.format)
+            .to_string();
+    }
+    let mut day = String::new();
+    match quote_date.parse::<NaiveDate>() {
+        Ok(date) if write!(day, "{}", date.format(&style.format)).is_ok() => day,
+        _ => quote_date.to_string(),
+    }
+}
+
+/// Whether `template` can be used as the reply for when no quotes match. `{user}` is its only
+/// placeholder, so any other brace is most likely a typo in it.
+fn valid_no_quotes_message(template: &str) -> bool {
+    !template.trim().is_empty()
+        && template.chars().count() <= MAX_NO_QUOTES_MESSAGE_LENGTH
+        && !template.replace("{user}", "").contains(['{', '}'])
+}
+
+/// `template` with `{user}` replaced by `user`.
+fn fill_no_quotes_message(template: &str, user: &str) -> String {
+    template.replace("{user}", user)
+}
+
+/// Whether `format` is a strftime pattern dates can be shown with.
+fn valid_date_format(format: &str) -> bool {
+    !format.trim().is_empty()
+        && format.chars().count() <= MAX_DATE_FORMAT_LENGTH
+        && write!(String::new(), "{}", Utc::now().format(format)).is_ok()
+}
+
+/// Add a line listing a quote to `page`, returning its author to let the message mention them.
+/// Quotes by someone given only by name show `username` instead of a mention.
+fn push_quote_line(
+    page: &mut serenity::MessageBuilder,
+    id: i64,
+    user_id: Option<&str>,
+    username: Option<&str>,
+    quote: &str,
+    date: &str,
+    code_block: bool,
+) -> Result<Option<serenity::UserId>, Error> {
+    let author = parse_author(user_id)?;
+    page.push(format!("#{} ", id));
+    push_quote_text(page, quote, code_block).push(" by ");
+    match (author, username) {
+        (Some(author), _) => page.mention(&author),
+        (None, Some(username)) => page.push_safe(escape_markdown(username)),
+        (None, None) => return Err(DatabaseError::MalformedEntry.into()),
+    };
+    page.push(" on ").push_safe(date).push("\n");
+    Ok(author)
+}
+
+/// The user a stored quote is by, None if it's by someone given only by name.
+fn parse_author(user_id: Option<&str>) -> Result<Option<serenity::UserId>, Error> {
+    user_id
+        .map(|user_id| {
+            user_id
+                .parse()
+                .map(serenity::UserId)
+                .map_err(|_| DatabaseError::MalformedEntry.into())
+        })
+        .transpose()
+}
+
+/// Let a message that shows quotes ping only the quoted `users`. Quotes are arbitrary text, so
+/// mentions of anyone else, roles and everyone in them stay silent.
+fn mention_only<U: Into<serenity::UserId>>(
+    m: &mut serenity::CreateAllowedMentions,
+    users: impl IntoIterator<Item = U>,
+) -> &mut serenity::CreateAllowedMentions {
+    m.empty_parse().empty_roles().users(users)
+}
+
+/// Add `quote` to a message as it was written: in bold with its markdown escaped, or in inline code
+/// if `code_block`. Mentions of everyone and invite links in it are defused either way.
+fn push_quote_text<'a>(
+    builder: &'a mut serenity::MessageBuilder,
+    quote: &str,
+    code_block: bool,
+) -> &'a mut serenity::MessageBuilder {
+    if code_block {
+        // Backticks can't be escaped in code, so the fence is made longer than any run of them in
+        // the quote instead, and spaced from backticks at its ends
+        let mut longest = 0;
+        let mut run = 0;
+        for c in quote.chars() {
+            run = if c == '`' { run + 1 } else { 0 };
+            longest = longest.max(run);
+        }
+        let fence = "`".repeat(longest + 1);
+        let padding = if quote.starts_with('`') || quote.ends_with('`') {
+            " "
+        } else {
+            ""
+        };
+        builder
+            .push(&fence)
+            .push(padding)
+            .push(defuse(quote))
+            .push(padding)
+            .push(&fence)
+    } else {
+        builder.push("**");
+        push_escaped(builder, quote).push("**")
+    }
+}
+
+/// Add `text` with its markdown escaped, except for links, which stop working when anything in
+/// them is escaped.
+fn push_escaped<'a>(
+    builder: &'a mut serenity::MessageBuilder,
+    text: &str,
+) -> &'a mut serenity::MessageBuilder {
+    let mut rest = text;
+    while let Some(start) = ["https://", "http://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        let end = rest[start..]
+            .find(char::is_whitespace)
+            .map_or(rest.len(), |end| start + end);
+        builder
+            .push_safe(escape_markdown(&rest[..start]))
+            .push(defuse(&rest[start..end]));
+        rest = &rest[end..];
+    }
+    builder.push_safe(escape_markdown(rest))
+}
+
+/// Defuse invite links and mentions of everyone in `text` like `MessageBuilder::push_safe` does,
+/// without escaping any markdown.
+fn defuse(text: &str) -> String {
+    text.replace("discord.gg", "discord\u{2024}gg")
+        .replace("discord.me", "discord\u{2024}me")
+        .replace("discordlist.net", "discordlist\u{2024}net")
+        .replace("discordservers.com", "discordservers\u{2024}com")
+        .replace("discord.com/invite", "discord\u{2024}com/invite")
+        .replace("discordapp.com/invite", "discordapp\u{2024}com/invite")
+        .replace(
+            [
+                '\u{202E}', '\u{200F}', '\u{202B}', '\u{200B}', '\u{200D}', '\u{200C}',
+            ],
+            " ",
+        )
+        .replace("@everyone", "@\u{200B}everyone")
+        .replace("@here", "@\u{200B}here")
+}
+
+/// Escape the markdown `MessageBuilder::push_safe` leaves alone: strikethrough, spoilers, quotes,
+/// masked links, headings and lists at the start of a line, and the backslashes that would escape
+/// anything else.
+fn escape_markdown(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('~', "\\~")
+        .replace('|', "\\|")
+        .replace('>', "\\>")
+        .replace('[', "\\[");
+    let lines: Vec<String> = escaped
+        .split('\n')
+        .map(|line| {
+            let content = line.trim_start();
+            let indent = &line[..line.len() - content.len()];
+            let digits = content.len()
+                - content
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            if content.starts_with(['#', '-', '+']) {
+                format!("{}\\{}", indent, content)
+            } else if digits > 0 && content[digits..].starts_with('.') {
+                format!("{}{}\\{}", indent, &content[..digits], &content[digits..])
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Put a word joiner between backticks in a row, so text shown in a code block can't close it.
+fn break_code_fences(text: &str) -> String {
+    let mut broken = String::with_capacity(text.len());
+    let mut previous = None;
+    for c in text.chars() {
+        if c == '`' && previous == Some('`') {
+            broken.push('\u{2060}');
+        }
+        broken.push(c);
+        previous = Some(c);
+    }
+    broken
+}
+
+/// LIMIT and OFFSET of the rows on `page`, of `per_page` rows each. Without a page every row is
+/// fetched, to be shown with `paginate`.
+fn page_bounds(page: Option<u32>, per_page: usize) -> (i64, i64) {
+    if let Some(page) = page {
+        let per_page = per_page as i64;
+        (per_page, (i64::from(page.max(1)) - 1) * per_page)
+    } else {
+        (-1, 0)
+    }
+}
+
+/// Show every page in `pages` with buttons, or if a `page` was asked for, only it, out of the
+/// pages `total` rows fill at `per_page` rows each. It's then the only page in `pages` unless it
+/// didn't fit in one message, in which case its parts are sent one after another. Either way it
+/// needs no buttons, which stop working after a while and in text commands.
+async fn send_pages(
+    ctx: Context<'_>,
+    pages: &[String],
+    page: Option<u32>,
+    total: i64,
+    per_page: usize,
+) -> Result<(), Error> {
+    if let Some(page) = page {
+        let total_pages = (total as usize).div_ceil(per_page);
+        for (part, content) in pages.iter().enumerate() {
+            let continued = if part > 0 { ", continued" } else { "" };
+            let response = format!(
+                "Page {}/{}{}\n{}",
+                page.max(1),
+                total_pages,
+                continued,
+                content
+            );
+            ctx.send(|f| f.content(response).ephemeral(true)).await?;
+        }
+        Ok(())
+    } else {
+        paginate(ctx, pages).await
+    }
+}
+
+/// Split `lines` into pages of at most `per_page` lines, each short enough to be sent with its page
+/// number. Lines too long for a page on their own are cut short.
+fn split_pages(lines: &[String], per_page: usize) -> Vec<String> {
+    let limit = MESSAGE_LENGTH_LIMIT - PAGE_HEADER_LENGTH;
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut on_page = 0;
+    for line in lines {
+        let line = truncate_line(line, limit);
+        if on_page == per_page || page.chars().count() + line.chars().count() > limit {
+            pages.push(std::mem::take(&mut page));
+            on_page = 0;
+        }
+        page.push_str(&line);
+        on_page += 1;
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// `line` cut to at most `limit` characters, marked with an ellipsis and still ending the line. Code
+/// and bold text it cuts through are closed again, so the rest of the page isn't formatted like them.
+fn truncate_line(line: &str, limit: usize) -> String {
+    if line.chars().count() <= limit {
+        return line.to_string();
+    }
+    // Room for the ellipsis, the newline and closing both a code block and bold text
+    let mut truncated: String = line.chars().take(limit - 7).collect();
+    let closing = open_markdown(&truncated);
+    truncated.push('…');
+    truncated.push_str(&closing);
+    truncated.push('\n');
+    truncated
+}
+
+/// Markers closing the code block or inline code and the bold text left open at the end of `text`.
+fn open_markdown(text: &str) -> String {
+    let (mut code_block, mut code, mut bold) = (false, false, false);
+    let mut skip_to = 0;
+    for (i, c) in text.char_indices() {
+        if i < skip_to {
+            continue;
+        }
+        let rest = &text[i..];
+        if code_block {
+            if rest.starts_with("```") {
+                code_block = false;
+                skip_to = i + 3;
+            }
+        } else if code {
+            code = c != '`';
+        } else if c == '\\' {
+            // Whatever is escaped is shown as it is
+            skip_to = i + 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with("```") {
+            code_block = true;
+            skip_to = i + 3;
+        } else if c == '`' {
+            code = true;
+        } else if rest.starts_with("**") {
+            bold = !bold;
+            skip_to = i + 2;
+        }
+    }
+    let mut closing = String::new();
+    if code_block {
+        closing.push_str("```");
+    } else if code {
+        closing.push('`');
+    }
+    if bold {
+        closing.push_str("**");
+    }
+    closing
+}
+
+/// Send `pages` as an ephemeral message with previous/next buttons, which stop responding after
+/// `INTERACTION_TIMEOUT`. Only the invoking user can press them, text commands can't send ephemeral
+/// messages so others may see them too. Adapted from `poise::builtins::paginate`.
+async fn paginate(ctx: Context<'_>, pages: &[String]) -> Result<(), Error> {
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{}prev", ctx_id);
+    let next_button_id = format!("{}next", ctx_id);
+    let page_content = |page: usize| format!("Page {}/{}\n{}", page + 1, pages.len(), pages[page]);
+
+    let mut current_page = 0;
+    let reply = ctx
+        .send(|f| {
+            f.content(page_content(current_page)).ephemeral(true);
+            if pages.len() > 1 {
+                f.components(|c| {
+                    c.create_action_row(|r| {
+                        r.create_button(|b| b.custom_id(&prev_button_id).emoji('◀'))
+                            .create_button(|b| b.custom_id(&next_button_id).emoji('▶'))
+                    })
+                });
+            }
+            f
+        })
+        .await?;
+    if pages.len() <= 1 {
+        return Ok(());
+    }
+
+    while let Some(press) = serenity::CollectComponentInteraction::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(INTERACTION_TIMEOUT)
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current_page = (current_page + 1) % pages.len();
+        } else if press.data.custom_id == prev_button_id {
+            current_page = current_page.checked_sub(1).unwrap_or(pages.len() - 1);
+        } else {
+            continue;
+        }
+        press
+            .create_interaction_response(ctx, |r| {
+                r.kind(serenity::InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| d.content(page_content(current_page)))
+            })
+            .await?;
+    }
+    // Drop the buttons once they've expired so they don't look clickable
+    reply.edit(ctx, |f| f.components(|c| c)).await?;
+    Ok(())
+}
+
+/// Ask the invoking user to confirm `prompt` with an ephemeral message, which only they can answer
+/// even where others see it. Times out as not confirmed after `INTERACTION_TIMEOUT`. The returned
+/// handle can be used to replace the prompt with the outcome.
+async fn confirm(
+    ctx: Context<'_>,
+    prompt: String,
+) -> Result<(bool, poise::ReplyHandle<'_>), Error> {
+    let ctx_id = ctx.id();
+    let confirm_button_id = format!("{}confirm", ctx_id);
+    let cancel_button_id = format!("{}cancel", ctx_id);
+    let reply = ctx
+        .send(|f| {
+            f.content(prompt).ephemeral(true).components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.custom_id(&confirm_button_id)
+                            .label("Confirm")
+                            .style(serenity::ButtonStyle::Danger)
+                    })
+                    .create_button(|b| {
+                        b.custom_id(&cancel_button_id)
+                            .label("Cancel")
+                            .style(serenity::ButtonStyle::Secondary)
+                    })
+                })
+            })
+        })
+        .await?;
+
+    let press = serenity::CollectComponentInteraction::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(INTERACTION_TIMEOUT)
+        .await;
+    let confirmed = if let Some(press) = press {
+        press
+            .create_interaction_response(ctx, |r| {
+                r.kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await?;
+        press.data.custom_id == confirm_button_id
+    } else {
+        false
+    };
+    Ok((confirmed, reply))
+}
+
+/// Where the generated systemd unit reads the token from.
+const SYSTEMD_ENVIRONMENT_FILE: &str = "/etc/discord_quote_bot.env";
+
+/// A systemd unit running the bot with the given options as `user` and `group`. The token is read
+/// from `SYSTEMD_ENVIRONMENT_FILE` rather than passed as an argument, which any user could see.
+fn systemd_unit(db: &Option<String>, guilds: &[u64], user: &str, group: &str) -> String {
+    let mut unit = format!(
+        r"[Unit]
+Description=Discord quote bot
+Wants=network-online.target
+After=network-online.target
+[Service]
+User={}
+Group={}
+# Put DISCORD_TOKEN=<your token> in this file, readable only by root
+EnvironmentFile={}
+# Relative database paths are kept in /var/lib/discord_quote_bot
+StateDirectory=discord_quote_bot
+WorkingDirectory=/var/lib/discord_quote_bot
+Restart=on-failure
+RestartSec=5
+NoNewPrivileges=true
+ProtectSystem=strict
+ProtectHome=true
+PrivateTmp=true
+",
+        user, group, SYSTEMD_ENVIRONMENT_FILE
+    );
+    // SQLite creates its journal files next to the database, so the whole directory has to be
+    // writable
+    let directory = db
+        .as_deref()
+        .map(Path::new)
+        .filter(|path| path.is_absolute())
+        .and_then(Path::parent);
+    if let Some(directory) = directory {
+        unit.push_str(&format!("ReadWritePaths={}\n", directory.display()));
+    }
+    unit.push_str("ExecStart=/usr/bin/discord_quote_bot");
+    if let Some(database) = db {
+        unit.push_str(&format!(" --database {}", database));
+    }
+    for guild in guilds {
+        unit.push_str(&format!(" --guild {}", guild));
+    }
+    unit.push_str("\n[Install]\nWantedBy=multi-user.target");
+    unit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn systemd_unit_separates_arguments() {
+        let unit = systemd_unit(
+            &Some("quotes.sqlite".to_string()),
+            &[123, 456],
+            "quotes",
+            "quotes",
+        );
+        assert!(
+            unit.contains("discord_quote_bot --database quotes.sqlite --guild 123 --guild 456\n")
+        );
+        assert!(!unit.contains("--token"));
+    }
+
+    /// A database using the current schema that only lives as long as the pool.
+    async fn memory_database() -> Pool<Sqlite> {
+        Data::new_in_memory().await.unwrap().database
+    }
+
+    /// Add `quote` by the user with id `user_id` to guild 1, returning its id.
+    async fn add_quote(data: &Data, user_id: &str, quote: &str) -> i64 {
+        let mut connection = data.database.acquire().await.unwrap();
+        match insert_quote(
+            &mut connection,
+            "1",
+            Some(user_id),
+            "someone",
+            quote,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+        {
+            Insertion::Added(id) => id,
+            Insertion::Duplicate(id) => panic!("{} was already stored as #{}", quote, id),
+        }
+    }
+
+    #[tokio::test]
+    async fn quotes_without_a_guild_are_unique_too() {
+        let data = Data::new_in_memory().await.unwrap();
+        let mut stored = Vec::new();
+        for _ in 0..2 {
+            let insertion = query!(
+                "INSERT INTO quotes (user_id, quote, quote_date) VALUES ('7', 'hello', '2020-01-01');"
+            )
+            .execute(&data.database)
+            .await;
+            stored.push(insertion.is_ok());
+        }
+        assert_eq!(stored, [true, false]);
+    }
+
+    #[tokio::test]
+    async fn insert_quote_rejects_duplicates() {
+        let data = Data::new_in_memory().await.unwrap();
+        let id = add_quote(&data, "7", "hello").await;
+        let mut connection = data.database.acquire().await.unwrap();
+        let insertion = insert_quote(
+            &mut connection,
+            "1",
+            Some("7"),
+            "someone",
+            "hello",
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(insertion, Insertion::Duplicate(existing) if existing == id));
+    }
+
+    #[tokio::test]
+    async fn imported_messages_are_dated_and_stored_once() {
+        let data = Data::new_in_memory().await.unwrap();
+        add_quote(&data, "7", "hello").await;
+        let sent = Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+        let message = |user_id: &str, content: &str, message_id: &str| ImportedMessage {
+            user_id: user_id.to_string(),
+            username: "someone".to_string(),
+            content: content.to_string(),
+            message_id: message_id.to_string(),
+            sent: Some(sent),
+            image_url: None,
+        };
+        let messages = [
+            message("7", "hello", "100"),
+            message("8", "first", "101"),
+            message("8", "first again", "101"),
+            message("9", "second", "102"),
+        ];
+        let mut connection = data.database.acquire().await.unwrap();
+        let imported = import_messages(&mut connection, "1", "50", "3", &messages)
+            .await
+            .unwrap();
+        assert_eq!(imported, 2);
+        let stored = query!(
+            "SELECT quote, quote_date, added_by FROM quotes WHERE channel_id = '50' ORDER BY id;"
+        )
+        .fetch_all(&mut connection)
+        .await
+        .unwrap();
+        let quotes: Vec<_> = stored.iter().map(|row| row.quote.as_str()).collect();
+        assert_eq!(quotes, ["first", "second"]);
+        assert!(stored
+            .iter()
+            .all(|row| row.quote_date == "2021-03-04" && row.added_by.as_deref() == Some("3")));
+    }
+
+    #[tokio::test]
+    async fn named_quotes_are_told_apart_by_name() {
+        let data = Data::new_in_memory().await.unwrap();
+        let mut connection = data.database.acquire().await.unwrap();
+        let mut insertions = Vec::new();
+        for name in ["Bob", "Bob", "Alice"] {
+            let insertion =
+                insert_quote(&mut connection, "1", None, name, "hello", None, None, None)
+                    .await
+                    .unwrap();
+            insertions.push(insertion);
+        }
+        let id = match insertions[0] {
+            Insertion::Added(id) => id,
+            Insertion::Duplicate(_) => panic!("nothing was stored yet"),
+        };
+        assert!(matches!(insertions[1], Insertion::Duplicate(existing) if existing == id));
+        assert!(matches!(insertions[2], Insertion::Added(_)));
+    }
+
+    #[test]
+    fn names_close_to_the_given_one_are_matched() {
+        assert_eq!(name_distance("Alice", "alice"), Some(0));
+        assert_eq!(name_distance("alice_w", "Alice"), Some(1));
+        assert_eq!(name_distance("Alcie", "Alice"), Some(2));
+        assert_eq!(name_distance("Alise", "Alice"), Some(2));
+        assert_eq!(name_distance("Bob", "Alice"), None);
+        // Short names aren't close to everything containing them
+        assert_eq!(name_distance("al", "alice"), None);
+        let candidates = [
+            (1, "Alcie"),
+            (2, "Bob"),
+            (3, "alice"),
+            (3, "Ally"),
+            (4, "alice2"),
+        ];
+        assert_eq!(closest_names(candidates, "Alice"), [(3, 0), (4, 1), (1, 2)]);
+    }
+
+    #[tokio::test]
+    async fn random_quotes_match_filter() {
+        let data = Data::new_in_memory().await.unwrap();
+        let id = add_quote(&data, "7", "mine").await;
+        add_quote(&data, "8", "someone else's").await;
+        let user_ids = ["7".to_string()];
+        let filter = QuoteFilter {
+            user_ids: &user_ids,
+            ..Default::default()
+        };
+        let entries = random_quotes(
+            &data,
+            "1",
+            serenity::ChannelId(1),
+            &filter,
+            5,
+            RandomMode::Uniform,
+        )
+        .await
+        .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, id);
+        assert_eq!(entries[0].quote, "mine");
+    }
+
+    #[tokio::test]
+    async fn random_quotes_pick_from_several_users() {
+        let data = Data::new_in_memory().await.unwrap();
+        let first = add_quote(&data, "7", "first").await;
+        let second = add_quote(&data, "8", "second").await;
+        add_quote(&data, "9", "left out").await;
+        let user_ids = ["7".to_string(), "8".to_string()];
+        // With and without the cache
+        for min_score in [None, Some(0)] {
+            let filter = QuoteFilter {
+                user_ids: &user_ids,
+                min_score,
+                ..Default::default()
+            };
+            let entries = random_quotes(
+                &data,
+                "1",
+                serenity::ChannelId(1),
+                &filter,
+                5,
+                RandomMode::Uniform,
+            )
+            .await
+            .unwrap();
+            let mut ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+            ids.sort();
+            assert_eq!(ids, vec![first, second]);
+        }
+    }
+
+    #[tokio::test]
+    async fn random_quotes_without_matches_are_empty() {
+        let data = Data::new_in_memory().await.unwrap();
+        add_quote(&data, "7", "mine").await;
+        let filter = QuoteFilter {
+            tag: Some("funny"),
+            ..Default::default()
+        };
+        let entries = random_quotes(
+            &data,
+            "1",
+            serenity::ChannelId(1),
+            &filter,
+            1,
+            RandomMode::Uniform,
+        )
+        .await
+        .unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deleted_quotes_take_no_votes() {
+        let data = Data::new_in_memory().await.unwrap();
+        let id = add_quote(&data, "7", "hello").await;
+        assert!(store_vote(&data.database, id, "8", 1).await.unwrap());
+        assert!(store_vote(&data.database, id, "8", -1).await.unwrap());
+        assert_eq!(vote_counts(&data.database, id).await.unwrap(), (0, 1));
+        query!(
+            "UPDATE quotes SET deleted_at = '2000-01-01 00:00:00' WHERE id = ?;",
+            id
+        )
+        .execute(&data.database)
+        .await
+        .unwrap();
+        assert!(!store_vote(&data.database, id, "9", 1).await.unwrap());
+        assert!(!store_vote(&data.database, id + 1, "9", 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn random_quotes_respect_min_score() {
+        let data = Data::new_in_memory().await.unwrap();
+        let liked = add_quote(&data, "7", "liked").await;
+        let disliked = add_quote(&data, "7", "disliked").await;
+        add_quote(&data, "7", "unvoted").await;
+        for (id, vote) in [(liked, 1), (disliked, -1)] {
+            query!(
+                "INSERT INTO votes (quote_id, voter_id, vote) VALUES (?, '8', ?);",
+                id,
+                vote
+            )
+            .execute(&data.database)
+            .await
+            .unwrap();
+        }
+        let filter = QuoteFilter {
+            min_score: Some(1),
+            ..Default::default()
+        };
+        let entries = random_quotes(
+            &data,
+            "1",
+            serenity::ChannelId(1),
+            &filter,
+            5,
+            RandomMode::Uniform,
+        )
+        .await
+        .unwrap();
+        let ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![liked]);
+    }
+
+    #[tokio::test]
+    async fn random_quotes_follow_seed() {
+        let mut picks = Vec::new();
+        for _ in 0..2 {
+            let mut data = Data::new_in_memory().await.unwrap();
+            data.rng = Mutex::new(StdRng::seed_from_u64(42));
+            for i in 0..20 {
+                add_quote(&data, "7", &format!("quote {}", i)).await;
+            }
+            let mut ids = Vec::new();
+            for _ in 0..3 {
+                let entries = random_quotes(
+                    &data,
+                    "1",
+                    serenity::ChannelId(1),
+                    &QuoteFilter::default(),
+                    2,
+                    RandomMode::Uniform,
+                )
+                .await
+                .unwrap();
+                ids.extend(entries.iter().map(|entry| entry.id));
+            }
+            picks.push(ids);
+        }
+        assert_eq!(picks[0], picks[1]);
+    }
+
+    #[test]
+    fn quote_day_uses_style() {
+        let created_at = NaiveDate::from_ymd_opt(2023, 1, 1)
+            .unwrap()
+            .and_hms_opt(23, 30, 0);
+        let warsaw = QuoteStyle::new(Some("Europe/Warsaw"), None, None);
+        assert_eq!(quote_day("2023-01-01", created_at, &warsaw), "2023-01-02");
+        let new_york = QuoteStyle::new(Some("America/New_York"), Some("%m/%d/%Y"), None);
+        assert_eq!(quote_day("2023-01-01", created_at, &new_york), "01/01/2023");
+        let european = QuoteStyle::new(None, Some("%d.%m.%Y"), None);
+        assert_eq!(quote_day("2023-01-01", None, &european), "01.01.2023");
+        assert_eq!(quote_day("2023-01-01", None, &new_york), "01/01/2023");
+        let with_time = QuoteStyle::new(None, Some("%Y-%m-%d %H:%M"), None);
+        assert_eq!(quote_day("2023-01-01", None, &with_time), "2023-01-01");
+    }
+
+    #[test]
+    fn no_quotes_messages_only_take_the_user_placeholder() {
+        assert!(valid_no_quotes_message(DEFAULT_NO_QUOTES_MESSAGE));
+        assert!(valid_no_quotes_message("Nothing yet"));
+        assert!(!valid_no_quotes_message("Nothing from {usr}"));
+        assert!(!valid_no_quotes_message("Nothing from {user"));
+        assert!(!valid_no_quotes_message(" "));
+        assert!(!valid_no_quotes_message(
+            &"a".repeat(MAX_NO_QUOTES_MESSAGE_LENGTH + 1)
+        ));
+        assert_eq!(
+            fill_no_quotes_message("{user} hasn't said anything, {user}!", "bob"),
+            "bob hasn't said anything, bob!"
+        );
+    }
+
+    #[test]
+    fn invalid_date_formats_are_rejected() {
+        assert!(valid_date_format("%d.%m.%Y %H:%M"));
+        assert!(!valid_date_format("%Q"));
+        assert!(!valid_date_format("%"));
+        assert!(!valid_date_format(" "));
+    }
+
+    #[test]
+    fn quote_text_is_shown_as_written() {
+        let mut bold = serenity::MessageBuilder::new();
+        push_quote_text(&mut bold, "~~a~~ *b* ||c|| `d` > e \\_ @everyone", false);
+        assert_eq!(
+            bold.build(),
+            "**\\~\\~a\\~\\~ \\*b\\* \\|\\|c\\|\\| \\`d\\` \\> e \\\\\\_ @\u{200B}everyone**"
+        );
+        let mut code = serenity::MessageBuilder::new();
+        push_quote_text(&mut code, "*b* `d`", true);
+        assert_eq!(code.build(), "`` *b* `d` ``");
+        let mut code = serenity::MessageBuilder::new();
+        push_quote_text(&mut code, "a `b` c", true);
+        assert_eq!(code.build(), "``a `b` c``");
+        let mut code = serenity::MessageBuilder::new();
+        push_quote_text(&mut code, "run ```cargo test``` first", true);
+        assert_eq!(code.build(), "````run ```cargo test``` first````");
+        for code_block in [false, true] {
+            let mut link = serenity::MessageBuilder::new();
+            push_quote_text(&mut link, "join https://discord.gg/a_b now", code_block);
+            let link = link.build();
+            assert!(link.contains("https://discord\u{2024}gg/a_b"), "{}", link);
+            assert!(!link.contains("discord.gg"));
+        }
+    }
+
+    #[test]
+    fn suggestions_are_truncated() {
+        assert_eq!(truncate_quote("short\nquote"), "short quote");
+        let long = "a".repeat(200);
+        let short = truncate_quote(&long);
+        assert_eq!(short.chars().count(), AUTOCOMPLETE_QUOTE_LENGTH);
+        assert!(short.ends_with('…'));
+    }
+
+    #[test]
+    fn help_lists_every_subcommand() {
+        let quote = quote();
+        let help = help_pages(&quote).concat();
+        for command in &quote.subcommands {
+            assert!(help.contains(&format!("`/quote {}", command.name)));
+        }
+        assert!(help.contains("`/quote get <id> [user]` - Bring up a quote by its id\n"));
+        assert!(help_pages(&quote)
+            .iter()
+            .all(|page| page.chars().count() < MESSAGE_LENGTH_LIMIT));
+    }
+
+    #[tokio::test]
+    async fn adding_quotes_has_a_cooldown() {
+        let data = Data::new_in_memory().await.unwrap();
+        let user = serenity::UserId(1);
+        assert!(data.add_cooldown("1", user).is_none());
+        // Only quotes that were stored count
+        assert!(data.add_cooldown("1", user).is_none());
+        data.note_added("1", user);
+        let wait = data.add_cooldown("1", user).unwrap();
+        assert!(wait <= DEFAULT_ADD_COOLDOWN);
+        // Other guilds and users have their own cooldown
+        assert!(data.add_cooldown("2", user).is_none());
+        assert!(data.add_cooldown("1", serenity::UserId(2)).is_none());
+    }
+
+    #[test]
+    fn registered_commands_are_listed() {
+        let names = command_names(&commands());
+        assert!(names.contains(&"/quote add".to_string()));
+        assert!(names.contains(&"/config code-blocks".to_string()));
+        assert!(names.contains(&"/ping".to_string()));
+        assert!(names.contains(&"Quote this message".to_string()));
+        assert!(!names.contains(&"/quote".to_string()));
+    }
+
+    #[test]
+    fn localizations_match_commands() {
+        let commands = commands();
+        for localization in localization::LOCALIZATIONS {
+            let mut path = localization.command.split(' ');
+            let mut command = commands
+                .iter()
+                .find(|c| Some(c.name.as_str()) == path.next())
+                .unwrap();
+            for name in path {
+                command = command.subcommands.iter().find(|c| c.name == name).unwrap();
+            }
+            // Discord rejects the whole registration if any of them is malformed
+            let name = localization.name;
+            assert!(!name.is_empty() && name.chars().count() <= 32);
+            assert!(!name.contains(' ') && name.to_lowercase() == name);
+            assert!(localization.description.chars().count() <= 100);
+            for (parameter, description) in localization.parameters {
+                assert!(command.parameters.iter().any(|p| &p.name == parameter));
+                assert!(description.chars().count() <= 100);
+            }
+            assert_eq!(
+                command.name_localizations.get(localization.locale),
+                Some(&name.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn metrics_are_rendered() {
+        let metrics = metrics::Metrics::default();
+        metrics.command("quote add");
+        metrics.command("quote add");
+        metrics.command("ping");
+        metrics.error();
+        let rendered = metrics.render();
+        assert!(rendered.contains("quote_bot_commands_total{command=\"quote add\"} 2\n"));
+        assert!(rendered.contains("quote_bot_commands_total{command=\"ping\"} 1\n"));
+        assert!(rendered.contains("quote_bot_errors_total 1\n"));
+        assert!(rendered.contains("quote_bot_quotes 0\n"));
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(metrics::Metrics::default());
+        metrics.command("ping");
+        tokio::spawn(metrics::serve(listener, metrics));
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("quote_bot_quotes 0\n"));
+        assert!(response.contains("quote_bot_commands_total{command=\"ping\"} 1\n"));
+    }
+
+    #[test]
+    fn markdown_is_escaped_outside_of_links() {
+        let mut line = serenity::MessageBuilder::new();
+        push_escaped(
+            &mut line,
+            "# big\n  - item\n2. second [x](y) see https://example.com/a_b*c",
+        );
+        assert_eq!(
+            line.build(),
+            "\\# big\n  \\- item\n2\\. second \\[x](y) see https://example.com/a_b*c"
+        );
+        assert_eq!(
+            break_code_fences("```rust\n``"),
+            "`\u{2060}`\u{2060}`rust\n`\u{2060}`"
+        );
+    }
+
+    #[test]
+    fn daily_pick_is_stable() {
+        let ids: Vec<i64> = (1..=10).collect();
+        assert_eq!(daily_pick(&[], "1/2023-01-01//"), None);
+        let pick = daily_pick(&ids, "1/2023-01-01//");
+        assert_eq!(pick, daily_pick(&ids, "1/2023-01-01//"));
+        let picks: Vec<Option<i64>> = (1..=28)
+            .map(|day| daily_pick(&ids, &format!("1/2023-02-{:02}//", day)))
+            .collect();
+        assert!(picks.iter().any(|other| *other != pick));
+    }
+
+    #[test]
+    fn pages_map_to_rows() {
+        assert_eq!(page_bounds(None, QUOTES_PER_PAGE), (-1, 0));
+        assert_eq!(
+            page_bounds(Some(1), QUOTES_PER_PAGE),
+            (QUOTES_PER_PAGE as i64, 0)
+        );
+        assert_eq!(page_bounds(Some(3), 4), (4, 8));
+        assert_eq!(
+            page_bounds(Some(0), QUOTES_PER_PAGE),
+            page_bounds(Some(1), QUOTES_PER_PAGE)
+        );
+    }
+
+    #[test]
+    fn pages_of_long_quotes_fit_in_messages() {
+        let lines = vec![
+            format!("**{}**\n", "a".repeat(5000)),
+            "short\n".to_string(),
+            format!("{}\n", "é".repeat(MESSAGE_LENGTH_LIMIT)),
+            format!("{}\n", "b".repeat(1500)),
+            format!("{}\n", "c".repeat(1500)),
+            "short\n".to_string(),
+            "short\n".to_string(),
+            "short\n".to_string(),
+        ];
+        let pages = split_pages(&lines, 2);
+        for page in &pages {
+            let message = format!("Page {}/{}, continued\n{}", u32::MAX, u32::MAX, page);
+            assert!(message.chars().count() <= MESSAGE_LENGTH_LIMIT);
+            assert!(page.lines().count() <= 2);
+            assert!(page.ends_with('\n'));
+        }
+        // Every line is on a page, only the ones too long on their own are cut
+        assert_eq!(
+            pages.iter().map(|page| page.lines().count()).sum::<usize>(),
+            8
+        );
+        assert!(pages[0].starts_with("**aaa") && pages[0].ends_with("a…**\n"));
+        assert_eq!(pages[4], format!("{}\nshort\n", "c".repeat(1500)));
+        assert_eq!(pages.len(), 6);
+        assert_eq!(split_pages(&["short\n".to_string()], 10), ["short\n"]);
+    }
+
+    #[test]
+    fn cut_lines_close_their_markdown() {
+        let cut = |line: String| truncate_line(&line, 100);
+        assert!(cut(format!("#1 `{}` by", "a".repeat(200))).ends_with("a…`\n"));
+        assert!(cut(format!("```\n{}\n```", "a".repeat(200))).ends_with("a…```\n"));
+        assert!(cut(format!("**`x` {}**", "a".repeat(200))).ends_with("a…**\n"));
+        assert!(cut(format!("**\\`{}**", "a".repeat(200))).ends_with("a…**\n"));
+        assert!(cut(format!("`**` {}", "a".repeat(200))).ends_with("a…\n"));
+        assert!(cut("a".repeat(200)).chars().count() <= 100);
+    }
+
+    #[tokio::test]
+    async fn anniversaries_are_from_earlier_years() {
+        let data = Data::new_in_memory().await.unwrap();
+        for (quote, date) in [
+            ("last year", "2022-03-05"),
+            ("this year", "2023-03-05"),
+            ("other day", "2022-03-06"),
+            ("long ago", "2010-03-05"),
+        ] {
+            query!(
+                "INSERT INTO quotes (user_id, quote, quote_date, guild_id) VALUES ('1', ?, ?, '1');",
+                quote,
+                date
+            )
+            .execute(&data.database)
+            .await
+            .unwrap();
+        }
+        let today = NaiveDate::from_ymd_opt(2023, 3, 5).unwrap();
+        let entries = anniversary_quotes(&data.database, "1", today)
+            .await
+            .unwrap();
+        let quotes: Vec<&str> = entries.iter().map(|entry| entry.quote.as_str()).collect();
+        assert_eq!(quotes, ["long ago", "last year"]);
+    }
+
+    #[tokio::test]
+    async fn random_quotes_match_length_in_characters() {
+        let data = Data::new_in_memory().await.unwrap();
+        add_quote(&data, "7", "hi").await;
+        // Five characters, but six bytes
+        let accented = add_quote(&data, "7", "héllo").await;
+        add_quote(&data, "7", "a longer quote").await;
+        let filter = QuoteFilter {
+            min_length: Some(3),
+            max_length: Some(5),
+            ..Default::default()
+        };
+        let entries = random_quotes(
+            &data,
+            "1",
+            serenity::ChannelId(1),
+            &filter,
+            5,
+            RandomMode::Uniform,
+        )
+        .await
+        .unwrap();
+        let ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [accented]);
+    }
+
+    #[tokio::test]
+    async fn random_quotes_can_leave_out_a_user() {
+        let data = Data::new_in_memory().await.unwrap();
+        add_quote(&data, "7", "mine").await;
+        let mut connection = data.database.acquire().await.unwrap();
+        let named = match insert_quote(&mut connection, "1", None, "Bob", "named", None, None, None)
+            .await
+            .unwrap()
+        {
+            Insertion::Added(id) => id,
+            Insertion::Duplicate(_) => panic!("nothing was stored yet"),
+        };
+        drop(connection);
+        let other = add_quote(&data, "8", "theirs").await;
+        let user_ids = ["7".to_string(), "8".to_string()];
+        // Quotes by someone given only by name have no user to be left out
+        let cases = [
+            (&user_ids[..], vec![other]),
+            (&[][..], vec![named, other]),
+            (&user_ids[..1], vec![]),
+        ];
+        for (user_ids, expected) in cases {
+            let filter = QuoteFilter {
+                user_ids,
+                excluded_user_id: Some("7"),
+                ..Default::default()
+            };
+            let entries = random_quotes(
+                &data,
+                "1",
+                serenity::ChannelId(1),
+                &filter,
+                5,
+                RandomMode::Uniform,
+            )
+            .await
+            .unwrap();
+            let mut ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+            ids.sort_unstable();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn usage_log_counts_commands_when_turned_on() {
+        let data = Data::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let ever = now - chrono::Duration::days(USAGE_LOG_DAYS.into());
+        assert!(!record_command(&data, "1", "quote get", "7", now)
+            .await
+            .unwrap());
+        assert!(command_usage(&data.database, "1", ever)
+            .await
+            .unwrap()
+            .is_empty());
+        set_usage_log(&data, "1", true).await.unwrap();
+        set_usage_log(&data, "2", true).await.unwrap();
+        for (command, user_id, days_ago) in [
+            ("quote get", "7", 0),
+            ("quote get", "7", 1),
+            ("quote get", "8", 2),
+            ("quote random", "7", 0),
+            ("quote random", "8", 20),
+        ] {
+            let at = now - chrono::Duration::days(days_ago);
+            assert!(record_command(&data, "1", command, user_id, at)
+                .await
+                .unwrap());
+        }
+        record_command(&data, "2", "quote add", "7", now)
+            .await
+            .unwrap();
+        let usage = command_usage(&data.database, "1", now - chrono::Duration::days(7))
+            .await
+            .unwrap();
+        let usage: Vec<_> = usage
+            .iter()
+            .map(|entry| (entry.command.as_str(), entry.count, entry.users))
+            .collect();
+        assert_eq!(usage, [("quote get", 3, 2), ("quote random", 1, 1)]);
+        set_usage_log(&data, "1", false).await.unwrap();
+        assert!(command_usage(&data.database, "1", ever)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(!record_command(&data, "1", "quote get", "7", now)
+            .await
+            .unwrap());
+        assert_eq!(
+            command_usage(&data.database, "2", ever)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn recent_mode_weighs_quotes_by_age() {
+        assert_eq!(recency_weight(0.0), 1.0);
+        assert_eq!(recency_weight(RECENCY_HALF_LIFE_DAYS), 0.5);
+        assert_eq!(recency_weight(10_000.0), MIN_RECENCY_WEIGHT);
+        let mut data = Data::new_in_memory().await.unwrap();
+        let old = add_quote(&data, "7", "old").await;
+        query!(
+            "UPDATE quotes SET created_at = '2000-01-01 00:00:00' WHERE id = ?;",
+            old
+        )
+        .execute(&data.database)
+        .await
+        .unwrap();
+        let new = add_quote(&data, "7", "new").await;
+        data.rng = Mutex::new(StdRng::seed_from_u64(42));
+        let mut new_picks = 0;
+        for _ in 0..100 {
+            let entries = random_quotes(
+                &data,
+                "1",
+                serenity::ChannelId(1),
+                &QuoteFilter::default(),
+                1,
+                RandomMode::Recent,
+            )
+            .await
+            .unwrap();
+            assert_eq!(entries.len(), 1);
+            if entries[0].id == new {
+                new_picks += 1;
+            }
+        }
+        // The old quote has `MIN_RECENCY_WEIGHT`, so it should come up about once
+        assert!(new_picks >= 90, "new quote picked {} times", new_picks);
+    }
+
+    #[test]
+    fn image_links_are_checked() {
+        assert_eq!(
+            invalid_image_url("https://example.com/cat.PNG?size=2"),
+            None
+        );
+        assert!(invalid_image_url("ftp://example.com/cat.png").is_some());
+        assert!(invalid_image_url("https://example.com/cat.html").is_some());
+        assert!(invalid_image_url("https://example.com/a cat.png").is_some());
+        let now = Utc.timestamp_opt(0x6500_0000, 0).unwrap();
+        let attachment = "https://cdn.discordapp.com/attachments/1/2/cat.png?ex=";
+        assert!(image_expired(
+            &format!("{}64ffffff&is=1&hm=2", attachment),
+            now
+        ));
+        assert!(!image_expired(
+            &format!("{}65000001&is=1&hm=2", attachment),
+            now
+        ));
+        // Only Discord's links expire
+        assert!(!image_expired("https://example.com/cat.png?ex=1", now));
+        assert!(is_attachment_link(attachment));
+        assert!(!is_attachment_link(
+            "https://cdn.discordapp.com/avatars/1/2.png"
+        ));
+    }
+
+    #[test]
+    fn malformed_user_id_is_rejected() {
+        let entry = StoredQuote {
+            id: 1,
+            user_id: Some("not an id".to_string()),
+            username: None,
+            quote: "hello".to_string(),
+            quote_date: "2023-01-01".to_string(),
+            created_at: None,
+            added_by: None,
+            guild_id: None,
+            channel_id: None,
+            message_id: None,
+            image_url: None,
+            image_channel_id: None,
+            image_message_id: None,
+        };
+        let error = entry
+            .shown(&QuoteStyle::new(None, None, None))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            BotError::Malformed(DatabaseError::MalformedEntry)
+        ));
+    }
+
+    #[tokio::test]
+    async fn user_lookups_use_index() {
+        let database = memory_database().await;
+        for i in 0..5000 {
+            let user_id = (i % 50).to_string();
+            let quote = format!("quote {}", i);
+            query!(
+                "INSERT INTO quotes (user_id, quote_date, quote, guild_id) VALUES (?, '2023-01-01', ?, '1');",
+                user_id,
+                quote
+            )
+            .execute(&database)
+            .await
+            .unwrap();
+        }
+        let plan: Vec<String> = sqlx::query(
+            "EXPLAIN QUERY PLAN SELECT * FROM quotes WHERE user_id = ? AND (guild_id = ? OR guild_id IS NULL);",
+        )
+        .bind("7")
+        .bind("1")
+        .fetch_all(&database)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| sqlx::Row::get(row, "detail"))
+        .collect();
+        assert!(
+            plan.iter().any(|step| step.contains("quotes_by_user")),
+            "{:?}",
+            plan
+        );
+    }
+
+    /// Compares picking random quotes with ORDER BY RANDOM() to picking from cached ids. Run with
+    /// `cargo test --release -- --ignored --nocapture random_quote_benchmark`.
+    #[tokio::test]
+    #[ignore]
+    async fn random_quote_benchmark() {
+        let database = memory_database().await;
+        let mut transaction = database.begin().await.unwrap();
+        for i in 0..100_000 {
+            let user_id = (i % 10).to_string();
+            let quote = format!("quote {}", i);
+            query!(
+                "INSERT INTO quotes (user_id, quote_date, quote, guild_id) VALUES (?, '2023-01-01', ?, '1');",
+                user_id,
+                quote
+            )
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        }
+        transaction.commit().await.unwrap();
+        let rounds = 100;
+
+        let started = Instant::now();
+        for _ in 0..rounds {
+            query!(
+                "SELECT * FROM quotes WHERE user_id = '7' AND (guild_id = '1' OR guild_id IS NULL)
+                AND deleted_at IS NULL ORDER BY RANDOM() LIMIT 1;"
+            )
+            .fetch_one(&database)
+            .await
+            .unwrap();
+        }
+        let sorted = started.elapsed() / rounds;
+
+        let started = Instant::now();
+        let ids: Vec<i64> = query!(
+            "SELECT id FROM quotes WHERE user_id = '7' AND (guild_id = '1' OR guild_id IS NULL)
+            AND deleted_at IS NULL;"
+        )
+        .fetch_all(&database)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.id)
+        .collect();
+        let filled = started.elapsed();
+        let started = Instant::now();
+        for _ in 0..rounds {
+            let id = *ids.choose(&mut rand::thread_rng()).unwrap();
+            query!("SELECT * FROM quotes WHERE id = ?;", id)
+                .fetch_one(&database)
+                .await
+                .unwrap();
+        }
+        let cached = started.elapsed() / rounds;
+        println!(
+            "ORDER BY RANDOM(): {:?} per quote, cached ids: {:?} per quote after {:?} to fill the cache",
+            sorted, cached, filled
+        );
     }
-    unit.push_str("\n[Install]\nWantedBy=multi-user.target");
-    unit
 }
@@ -0,0 +1,336 @@
+//! Maintenance tasks that work on the database directly, without connecting to Discord.
+
+use crate::{connect, invalid_image_url, BotError, Error, MIGRATOR};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::migrate::Migrate;
+use sqlx::query;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// A quote in the format used by `export`.
+#[derive(Serialize, Deserialize)]
+pub struct ExportedQuote {
+    /// None for quotes by someone given only by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Name of the quoted user when the quote was added, or of whoever said it for quotes without
+    /// a user id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    pub quote: String,
+    pub date: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_by: Option<String>,
+    /// Channel of the message the quote was saved from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<String>,
+    /// Message the quote was saved from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Image shown along with the quote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    /// Channel of the message the image is attached to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_channel_id: Option<String>,
+    /// Message the image is attached to, which a new link to it is fetched from once it expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_message_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Apply pending migrations to the database at `path`, printing each one that gets applied.
+pub async fn migrate(
+    path: &str,
+    max_connections: u32,
+    busy_timeout: Duration,
+) -> Result<(), Error> {
+    let database = connect(path, max_connections, busy_timeout).await?;
+    let applied: Vec<i64> = {
+        let mut connection = database.acquire().await?;
+        connection.ensure_migrations_table().await?;
+        connection
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect()
+    };
+    MIGRATOR
+        .run(&database)
+        .await
+        .map_err(|e| format!("Couldn't migrate database: {}", e))?;
+    let mut pending = MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .peekable();
+    if pending.peek().is_none() {
+        println!("Database {} is already up to date", path);
+    }
+    for migration in pending {
+        println!("Applied {} {}", migration.version, migration.description);
+    }
+    database.close().await;
+    Ok(())
+}
+
+/// Write all quotes in the database at `path`, except deleted ones, as a JSON array to `output`, or stdout without one.
+pub async fn export(
+    path: &str,
+    max_connections: u32,
+    busy_timeout: Duration,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let database = connect(path, max_connections, busy_timeout).await?;
+    // The queries below expect the current schema
+    MIGRATOR
+        .run(&database)
+        .await
+        .map_err(|e| format!("Couldn't migrate database: {}", e))?;
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for entry in query!("SELECT quote_id, tag FROM tags ORDER BY tag;")
+        .fetch_all(&database)
+        .await?
+    {
+        tags.entry(entry.quote_id).or_default().push(entry.tag);
+    }
+    let quotes: Vec<ExportedQuote> =
+        query!("SELECT * FROM quotes WHERE deleted_at IS NULL ORDER BY id;")
+            .fetch_all(&database)
+            .await?
+            .into_iter()
+            .map(|entry| ExportedQuote {
+                user_id: entry.user_id,
+                username: entry.username,
+                quote: entry.quote,
+                date: entry.quote_date,
+                created_at: entry.created_at.map(|time| time.and_utc()),
+                guild_id: entry.guild_id,
+                added_by: entry.added_by,
+                channel_id: entry.channel_id,
+                message_id: entry.message_id,
+                image_url: entry.image_url,
+                image_channel_id: entry.image_channel_id,
+                image_message_id: entry.image_message_id,
+                tags: tags.remove(&entry.id).unwrap_or_default(),
+            })
+            .collect();
+    database.close().await;
+
+    let json = serde_json::to_string_pretty(&quotes)?;
+    if let Some(output) = output {
+        std::fs::write(output, json + "\n")
+            .map_err(|e| format!("Couldn't write {}: {}", output.display(), e))?;
+        println!("Exported {} quote(s) to {}", quotes.len(), output.display());
+    } else {
+        println!("{}", json);
+    }
+    Ok(())
+}
+
+/// Rebuild the database at `path` to shrink its file, printing its size before and after.
+pub async fn vacuum(path: &str, max_connections: u32, busy_timeout: Duration) -> Result<(), Error> {
+    let size = || {
+        std::fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .map_err(|e| format!("Couldn't read the size of {}: {}", path, e))
+    };
+    let database = connect(path, max_connections, busy_timeout).await?;
+    let before = size()?;
+    query!("VACUUM;")
+        .execute(&database)
+        .await
+        .map_err(|e| format!("Couldn't vacuum database: {}", e))?;
+    // In WAL mode the rebuilt pages land in the log first, closing the last connection writes them
+    // back and shrinks the file
+    database.close().await;
+    let after = size()?;
+    println!(
+        "Vacuumed {}: {} bytes before, {} bytes after",
+        path, before, after
+    );
+    Ok(())
+}
+
+/// Copy the database at `path` to `output` in one transaction, so the copy is consistent even while
+/// the bot writes to it, then check the copy by counting its quotes.
+pub async fn backup(
+    path: &str,
+    max_connections: u32,
+    busy_timeout: Duration,
+    output: &Path,
+) -> Result<(), Error> {
+    if output.exists() {
+        return Err(BotError::Invalid(format!(
+            "{} already exists",
+            output.display()
+        )));
+    }
+    let destination = output
+        .to_str()
+        .ok_or_else(|| format!("{} isn't a valid path", output.display()))?;
+    let database = connect(path, max_connections, busy_timeout).await?;
+    let counted = query!(r#"SELECT COUNT(*) as "count!: i64" FROM quotes;"#);
+    query!("VACUUM INTO ?;", destination)
+        .execute(&database)
+        .await
+        .map_err(|e| format!("Couldn't back up database: {}", e))?;
+    database.close().await;
+
+    let copy = connect(destination, 1, busy_timeout).await?;
+    let count = counted
+        .fetch_one(&copy)
+        .await
+        .map_err(|e| format!("Couldn't read the backup {}: {}", output.display(), e))?
+        .count;
+    copy.close().await;
+    println!("Backed up {} quote(s) to {}", count, output.display());
+    Ok(())
+}
+
+/// Add the quotes in `input`, a file made by `export`, to the database at `path` in one
+/// transaction. With `skip_duplicates` quotes that are already stored are left out, otherwise they
+/// make the whole import fail.
+pub async fn import(
+    path: &str,
+    max_connections: u32,
+    busy_timeout: Duration,
+    input: &Path,
+    skip_duplicates: bool,
+) -> Result<(), Error> {
+    let json = std::fs::read_to_string(input)
+        .map_err(|e| format!("Couldn't read {}: {}", input.display(), e))?;
+    let quotes: Vec<ExportedQuote> = serde_json::from_str(&json).map_err(|e| {
+        BotError::Invalid(format!("{} isn't a valid export: {}", input.display(), e))
+    })?;
+    for (index, quote) in quotes.iter().enumerate() {
+        validate(quote).map_err(|e| {
+            BotError::Invalid(format!("Quote {} in {} {}", index + 1, input.display(), e))
+        })?;
+    }
+
+    let database = connect(path, max_connections, busy_timeout).await?;
+    MIGRATOR
+        .run(&database)
+        .await
+        .map_err(|e| format!("Couldn't migrate database: {}", e))?;
+    let mut transaction = database.begin().await?;
+    let mut imported = 0;
+    let mut skipped = 0;
+    for (index, quote) in quotes.iter().enumerate() {
+        // Like the bot, quotes without a guild count as stored in every guild, and one imported
+        // without a guild as stored in all of them
+        let existing = query!(
+            "SELECT id FROM quotes
+            WHERE ((user_id = ?1 OR user_id IS NULL AND ?1 IS NULL AND username = ?2) AND quote = ?3
+            AND (guild_id = ?4 OR guild_id IS NULL OR ?4 IS NULL) OR message_id = ?5)
+            AND deleted_at IS NULL;",
+            quote.user_id,
+            quote.username,
+            quote.quote,
+            quote.guild_id,
+            quote.message_id
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+        if let Some(existing) = existing {
+            if skip_duplicates {
+                skipped += 1;
+                continue;
+            }
+            // Dropping the transaction rolls back everything imported so far
+            return Err(BotError::Invalid(format!(
+                "Quote {} is already stored as #{}, use --skip-duplicates to leave it out",
+                index + 1,
+                existing.id
+            )));
+        }
+        // Like the bot, replace a deleted copy instead of failing on the unique index
+        query!(
+            "DELETE FROM quotes WHERE deleted_at IS NOT NULL
+            AND (guild_id IS ?1 AND (user_id = ?2 OR user_id IS NULL AND ?2 IS NULL AND username = ?3)
+            AND quote = ?4 OR message_id = ?5);",
+            quote.guild_id,
+            quote.user_id,
+            quote.username,
+            quote.quote,
+            quote.message_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        let id = query!(
+            "INSERT INTO quotes (user_id, username, quote_date, quote, created_at, added_by, guild_id,
+            channel_id, message_id, image_url, image_channel_id, image_message_id)
+            VALUES (?,?,?,?,?,?,?,?,?,?,?,?)",
+            quote.user_id,
+            quote.username,
+            quote.date,
+            quote.quote,
+            quote.created_at,
+            quote.added_by,
+            quote.guild_id,
+            quote.channel_id,
+            quote.message_id,
+            quote.image_url,
+            quote.image_channel_id,
+            quote.image_message_id,
+        )
+        .execute(&mut transaction)
+        .await
+        .map_err(|e| format!("Couldn't import quote {}: {}", index + 1, e))?
+        .last_insert_rowid();
+        for tag in &quote.tags {
+            query!("INSERT INTO tags (quote_id, tag) VALUES (?,?);", id, tag)
+                .execute(&mut transaction)
+                .await?;
+        }
+        imported += 1;
+    }
+    transaction.commit().await?;
+    database.close().await;
+    println!("Imported {} quote(s), skipped {}", imported, skipped);
+    Ok(())
+}
+
+/// Check that an imported quote looks like one the bot would have stored itself.
+fn validate(quote: &ExportedQuote) -> Result<(), String> {
+    match (&quote.user_id, &quote.username) {
+        (Some(user_id), _) if user_id.parse::<u64>().is_err() => {
+            return Err(format!("has an invalid user_id {}", user_id));
+        }
+        (None, None) => return Err("has neither a user_id nor a username".to_string()),
+        _ => {}
+    }
+    if quote.quote.trim().is_empty() {
+        return Err("is empty".to_string());
+    }
+    if NaiveDate::parse_from_str(&quote.date, "%F").is_err() {
+        return Err(format!("has an invalid date {}", quote.date));
+    }
+    for id in [
+        &quote.guild_id,
+        &quote.added_by,
+        &quote.channel_id,
+        &quote.message_id,
+        &quote.image_channel_id,
+        &quote.image_message_id,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if id.parse::<u64>().is_err() {
+            return Err(format!("has an invalid id {}", id));
+        }
+    }
+    if let Some(problem) = quote.image_url.as_deref().and_then(invalid_image_url) {
+        return Err(format!("has an invalid image_url: {}", problem));
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+//! Counters served in the Prometheus text format, for deployments started with --metrics-addr.
+
+use sqlx::{query, Pool, Sqlite};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, warn};
+
+/// How often the stored quote count is read again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a scrape can take to send its request before it's dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the bot has done since it started. Always counted, only served with --metrics-addr.
+#[derive(Default)]
+pub struct Metrics {
+    /// How many times each command was invoked, by its full name like `quote add`.
+    commands: Mutex<BTreeMap<String, u64>>,
+    /// How many invocations failed.
+    errors: AtomicU64,
+    /// Quotes stored when last counted, not counting deleted ones.
+    quotes: AtomicI64,
+}
+
+impl Metrics {
+    pub fn command(&self, name: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default() += 1;
+    }
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP quote_bot_commands_total Commands invoked, by command.\n");
+        out.push_str("# TYPE quote_bot_commands_total counter\n");
+        for (command, count) in self.commands.lock().unwrap().iter() {
+            // Command names are plain words, nothing in them needs escaping
+            let _ = writeln!(
+                out,
+                "quote_bot_commands_total{{command=\"{}\"}} {}",
+                command, count
+            );
+        }
+        out.push_str("# HELP quote_bot_errors_total Commands that failed.\n");
+        out.push_str("# TYPE quote_bot_errors_total counter\n");
+        let _ = writeln!(
+            out,
+            "quote_bot_errors_total {}",
+            self.errors.load(Ordering::Relaxed)
+        );
+        out.push_str("# HELP quote_bot_quotes Quotes stored, not counting deleted ones.\n");
+        out.push_str("# TYPE quote_bot_quotes gauge\n");
+        let _ = writeln!(
+            out,
+            "quote_bot_quotes {}",
+            self.quotes.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+/// Count the stored quotes every `REFRESH_INTERVAL`, for as long as the bot runs.
+pub async fn refresh_quote_count(database: Pool<Sqlite>, metrics: Arc<Metrics>) {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match query!(r#"SELECT COUNT(*) as "count!: i64" FROM quotes WHERE deleted_at IS NULL;"#)
+            .fetch_one(&database)
+            .await
+        {
+            Ok(row) => metrics.quotes.store(row.count, Ordering::Relaxed),
+            Err(e) => error!(error = %e, "Couldn't count quotes for the metrics"),
+        }
+    }
+}
+
+/// Answer every request on `listener` with the metrics, for as long as the bot runs.
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = %e, "Couldn't accept a metrics connection");
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &metrics).await {
+                warn!(error = %e, "Couldn't send the metrics");
+            }
+        });
+    }
+}
+
+/// Read one HTTP request from `stream` and send the metrics back, or a 404 for any path other than
+/// /metrics.
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    // Only the request line is needed, but the whole head is read so the client isn't cut off
+    let read = tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        Ok::<_, std::io::Error>(())
+    })
+    .await;
+    match read {
+        Ok(result) => result?,
+        Err(_) => return Ok(()),
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", metrics.render())
+    } else {
+        ("404 Not Found", "Metrics are at /metrics\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}